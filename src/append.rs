@@ -1,53 +1,40 @@
 use std::cmp::Reverse;
-use std::fmt::Display;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use calamine::{Reader as _, Xlsx};
+use calamine::{Data, Reader as _, Xlsx, XlsxError};
 use rust_xlsxwriter::workbook::Workbook;
 use rust_xlsxwriter::{Format, Table, TableStyle};
 
-use crate::prelude::Result;
-use crate::{ExcelLoggable, FieldLengsths, HasDateTime, WORKSHEET_NAME};
+use crate::prelude::{Error, Result};
+use crate::{ExcelLoggable, FieldLengsths, HasDateTime};
 
-pub async fn append_log<S, E>(base_path: S, file_base: S, new_entry: E) -> Result<()>
+/// Merges `new_entries` into the workbook at `path`, keeping the sheet sorted newest first.
+/// The previous version is kept as `<path>.bak`, the directory must already exist.
+pub async fn append_log<E>(worksheet_name: &str, path: &Path, new_entries: Vec<E>) -> Result<()>
 where
-    S: AsRef<str> + Display,
     E: ExcelLoggable + HasDateTime + Send + 'static,
     E: FieldLengsths,
 {
-    let base_path = base_path.as_ref();
-    let file_base = file_base.as_ref();
-    let path = PathBuf::from(base_path).join(format!("{file_base}.xlsx"));
-
+    let path = path.to_path_buf();
     let new_path = path.clone();
+    let worksheet_name = worksheet_name.to_string();
+    let read_worksheet_name = worksheet_name.clone();
 
-    let entries: Vec<E> = tokio::task::spawn_blocking(move || -> Result<Vec<E>> {
-        let mut existing = vec![];
-        if path.exists() {
-            let mut wb: Xlsx<_> = calamine::open_workbook(&path)?;
-            if let Ok(range) = wb.worksheet_range(WORKSHEET_NAME) {
-                // skip header
-                for r in range.rows().skip(1) {
-                    if let Some(e) = E::parse_row(r) {
-                        // E::parse_row(r) {
-                        existing.push(e);
-                    }
-                }
-            }
-        }
-        existing.push(new_entry);
+    let (entries, recovered) = tokio::task::spawn_blocking(move || -> Result<(Vec<E>, bool)> {
+        let (sheet, recovered) = read_existing(&path, &read_worksheet_name)?;
+        let mut existing = sheet.entries;
+        existing.extend(new_entries);
         existing.sort_by_key(|e| Reverse(e.date_time()));
-        Ok(existing)
+        Ok((existing, recovered))
     })
     .await??;
 
     let path = new_path.clone();
 
     tokio::task::spawn_blocking(move || -> Result<()> {
-        std::fs::create_dir(path.parent().unwrap())?;
         let mut workbook = Workbook::new();
         let ws = workbook.add_worksheet();
-        ws.set_name(WORKSHEET_NAME)?;
+        ws.set_name(&worksheet_name)?;
 
         let bold = Format::new().set_bold();
         let date_fmt = Format::new().set_num_format("yyyy/mm/dd hh:mm AM/PM");
@@ -77,18 +64,211 @@ where
             e.write_entry(ws, (i + 1) as u32)?;
         }
 
-        // table + formatting
+        // table + formatting, the table brings its own autofilter (a worksheet one over it is an error)
         if !entries.is_empty() {
             let table = Table::new().set_style(TableStyle::Medium9);
             ws.add_table(0, 0, entries.len() as u32, E::COLUMNS.len() as u16 - 1, &table)?;
-            ws.autofilter(0, 0, entries.len() as u32, E::COLUMNS.len() as u16 - 1)?;
         }
         ws.set_freeze_panes(1, 0)?;
 
-        workbook.save(path)?;
+        // A broken workbook isn't worth keeping as the backup we just recovered from
+        save_atomic(&mut workbook, &path, !recovered)?;
         Ok(())
     })
     .await??;
 
     Ok(())
 }
+
+// `<file>.xlsx.<ext>` next to the workbook.
+fn sibling(path: &Path, ext: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(format!(".{ext}"));
+    PathBuf::from(sibling)
+}
+
+/// `<workbook>.bak`, the version before the last save.
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, "bak")
+}
+
+/// A worksheet as `read_existing` found it.
+pub struct Sheet<E> {
+    /// `None` when there's no worksheet (or workbook) yet.
+    pub header:   Option<Vec<Data>>,
+    pub entries:  Vec<E>,
+    /// Sheet row numbers (1 is the header) of rows `E::parse_row` couldn't read.
+    pub bad_rows: Vec<u32>,
+}
+
+impl<E> Default for Sheet<E> {
+    fn default() -> Self {
+        Self {
+            header:   None,
+            entries:  vec![],
+            bad_rows: vec![],
+        }
+    }
+}
+
+/// Rows already in the workbook. A missing or corrupt workbook (eg: cut off mid-save) falls back to
+/// the `.bak`, failing rather than starting the day over when neither can be read. Errors reading the
+/// file at all (someone has it open in Excel, the share went away) are returned as they are: the
+/// workbook is probably fine and the `.bak` is a version behind.
+/// The flag is set when the rows came from the `.bak`.
+pub fn read_existing<E: ExcelLoggable>(path: &Path, worksheet_name: &str) -> Result<(Sheet<E>, bool)> {
+    let backup = backup_path(path);
+
+    let primary_error = match read_rows(path, worksheet_name) {
+        Ok(Some(rows)) => return Ok((rows, false)),
+        Ok(None) => None,
+        Err(e) if is_corrupt(&e) => Some(e),
+        Err(e) => return Err(e),
+    };
+
+    match (read_rows(&backup, worksheet_name), primary_error) {
+        (Ok(Some(rows)), primary_error) => {
+            match primary_error {
+                Some(e) => {
+                    tracing::warn!(
+                        "{} is unreadable ({}), recovering from {}",
+                        path.display(),
+                        e,
+                        backup.display()
+                    )
+                }
+                None => tracing::warn!("{} is missing, recovering from {}", path.display(), backup.display()),
+            }
+            Ok((rows, true))
+        }
+        (Ok(None), None) => Ok((Sheet::default(), false)),
+        (Ok(None), Some(e)) => Err(e),
+        (Err(backup_error), Some(e)) => {
+            tracing::error!("{} is unreadable too: {}", backup.display(), backup_error);
+            Err(e)
+        }
+        (Err(backup_error), None) => Err(backup_error),
+    }
+}
+
+// The file could be read but isn't a workbook (bad zip, bad XML, truncated), as opposed to an I/O
+// error getting at it. Truncated or mangled compressed data surfaces as `InvalidData` /
+// `UnexpectedEof` I/O errors from inside the zip reader.
+fn is_corrupt(e: &Error) -> bool {
+    let Error::CalamaineXlsxError(e) = e else {
+        return false;
+    };
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            return matches!(io.kind(), std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof);
+        }
+        source = e.source();
+    }
+    true
+}
+
+// `None` when there's no such file.
+fn read_rows<E: ExcelLoggable>(path: &Path, worksheet_name: &str) -> Result<Option<Sheet<E>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut sheet = Sheet::default();
+    let mut wb: Xlsx<_> = calamine::open_workbook(path)?;
+    let range = match wb.worksheet_range(worksheet_name) {
+        Ok(range) => range,
+        // Nothing of ours in it yet
+        Err(XlsxError::WorksheetNotFound(_)) => return Ok(Some(sheet)),
+        Err(e) => return Err(e.into()),
+    };
+    let mut rows = range.rows();
+    sheet.header = rows.next().map(<[Data]>::to_vec);
+    for (i, r) in rows.enumerate() {
+        match E::parse_row(r) {
+            Some(e) => sheet.entries.push(e),
+            None => sheet.bad_rows.push(i as u32 + 2),
+        }
+    }
+    Ok(Some(sheet))
+}
+
+// Saves to `<file>.tmp`, then swaps it in: the current workbook becomes `<file>.bak` (when
+// `keep_backup`, otherwise it's replaced) and the temp file takes its place. Between the two renames
+// there is no workbook at all, readers (and a crash) in that moment find only the `.bak`, which
+// `read_existing` recovers from. Nothing ever sees a half-written workbook.
+fn save_atomic(workbook: &mut Workbook, path: &Path, keep_backup: bool) -> Result<()> {
+    let tmp = sibling(path, "tmp");
+    workbook.save(&tmp)?;
+    std::fs::File::open(&tmp)?.sync_all()?;
+
+    if keep_backup && path.exists() {
+        std::fs::rename(path, sibling(path, "bak"))?;
+    }
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::*;
+    use crate::workstation::WorkStationEntry;
+
+    fn entry(username: &str) -> WorkStationEntry {
+        WorkStationEntry::sample(username, Local.with_ymd_and_hms(2026, 3, 2, 8, 40, 0).unwrap())
+    }
+
+    #[tokio::test]
+    async fn corrupt_workbook_recovers_from_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.xlsx");
+        append_log("Logons", &path, vec![entry("first")]).await.unwrap();
+        append_log("Logons", &path, vec![entry("second")]).await.unwrap();
+        // Cut off mid-save, the `.bak` holds `first`
+        std::fs::write(&path, b"PK\x03\x04 not really").unwrap();
+
+        append_log("Logons", &path, vec![entry("third")]).await.unwrap();
+        let (sheet, recovered) = read_existing::<WorkStationEntry>(&path, "Logons").unwrap();
+        assert!(!recovered);
+        let mut users: Vec<_> = sheet.entries.iter().map(|e| e.username.as_str()).collect();
+        users.sort();
+        assert_eq!(users, ["first", "third"]);
+    }
+
+    #[tokio::test]
+    async fn rows_read_back_get_the_same_ids_every_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.xlsx");
+        append_log("Logons", &path, vec![entry("first"), entry("second")])
+            .await
+            .unwrap();
+
+        let ids = || -> Vec<uuid::Uuid> {
+            let (sheet, _) = read_existing::<WorkStationEntry>(&path, "Logons").unwrap();
+            sheet.entries.iter().map(|e| e.entry_id).collect()
+        };
+        let first_read = ids();
+        assert_eq!(first_read, ids());
+        assert_ne!(first_read[0], first_read[1]);
+        assert!(!first_read.contains(&uuid::Uuid::nil()));
+    }
+
+    #[tokio::test]
+    async fn unreadable_workbook_is_an_error_not_a_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.xlsx");
+        append_log("Logons", &path, vec![entry("first")]).await.unwrap();
+        append_log("Logons", &path, vec![entry("second")]).await.unwrap();
+        // Stands in for a sharing violation: there, but can't be opened
+        std::fs::rename(&path, dir.path().join("moved.xlsx")).unwrap();
+        std::fs::create_dir(&path).unwrap();
+
+        let Err(err) = read_existing::<WorkStationEntry>(&path, "Logons") else {
+            panic!("read a directory as a workbook");
+        };
+        assert!(!is_corrupt(&err), "{err}");
+        assert!(append_log("Logons", &path, vec![entry("third")]).await.is_err());
+    }
+}
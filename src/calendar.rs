@@ -0,0 +1,313 @@
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::config::{deserialize_date, deserialize_opt_date};
+use crate::ics::{IcsMapping, import_ics};
+use crate::prelude::{Error, Result};
+
+pub const DEFAULT_HOLIDAY_LABEL: &str = "Holiday";
+pub const DEFAULT_STAFF_DAY_LABEL: &str = "Staff Day";
+
+/// A named, inclusive date range.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DateRange {
+    pub name:  String,
+    #[serde(deserialize_with = "deserialize_date")]
+    pub start: NaiveDate,
+    /// Defaults to `start` (single day).
+    #[serde(default, deserialize_with = "deserialize_opt_date")]
+    pub end:   Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn new(name: impl Into<String>, start: NaiveDate, end: NaiveDate) -> Self {
+        Self {
+            name: name.into(),
+            start,
+            end: Some(end),
+        }
+    }
+
+    pub fn end(&self) -> NaiveDate {
+        self.end.unwrap_or(self.start)
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        date >= self.start && date <= self.end()
+    }
+}
+
+/// `[calendar]` as written in the config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarDef {
+    pub holiday_label:   String,
+    pub staff_day_label: String,
+    pub terms:           Vec<DateRange>,
+    pub holidays:        Vec<DateRange>,
+    pub staff_days:      Vec<DateRange>,
+    /// Extra dates imported from the school's calendar export.
+    pub ics:             Option<IcsImportDef>,
+}
+
+impl Default for CalendarDef {
+    fn default() -> Self {
+        Self {
+            holiday_label:   DEFAULT_HOLIDAY_LABEL.to_string(),
+            staff_day_label: DEFAULT_STAFF_DAY_LABEL.to_string(),
+            terms:           vec![],
+            holidays:        vec![],
+            staff_days:      vec![],
+            ics:             None,
+        }
+    }
+}
+
+// No `deny_unknown_fields` here, serde doesn't support it together with `flatten`
+#[derive(Debug, Clone, Deserialize)]
+pub struct IcsImportDef {
+    pub path:    PathBuf,
+    #[serde(flatten)]
+    pub mapping: IcsMapping,
+}
+
+/// What kind of day a date is, as far as period resolution cares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DayKind<'a> {
+    School,
+    /// Public holiday, or outside of every term.
+    Holiday {
+        label: &'a str,
+        name:  &'a str,
+    },
+    StaffDay {
+        label: &'a str,
+        name:  &'a str,
+    },
+}
+
+/// Term dates plus the closures inside/around them. Without any terms configured every day is a school day.
+#[derive(Debug, Clone)]
+pub struct TermCalendar {
+    holiday_label:   String,
+    staff_day_label: String,
+    terms:           Vec<DateRange>,
+    holidays:        Vec<DateRange>,
+    staff_days:      Vec<DateRange>,
+}
+
+impl Default for TermCalendar {
+    fn default() -> Self {
+        Self {
+            holiday_label:   DEFAULT_HOLIDAY_LABEL.to_string(),
+            staff_day_label: DEFAULT_STAFF_DAY_LABEL.to_string(),
+            terms:           vec![],
+            holidays:        vec![],
+            staff_days:      vec![],
+        }
+    }
+}
+
+impl TermCalendar {
+    /// `resolve` maps the `.ics` path to a real path (the config resolves it relative to itself).
+    pub fn build(def: &CalendarDef, resolve: impl Fn(&Path) -> PathBuf) -> Result<Self> {
+        let mut calendar = Self {
+            holiday_label:   def.holiday_label.clone(),
+            staff_day_label: def.staff_day_label.clone(),
+            terms:           def.terms.clone(),
+            holidays:        def.holidays.clone(),
+            staff_days:      def.staff_days.clone(),
+        };
+
+        if let Some(ics) = &def.ics {
+            let path = resolve(&ics.path);
+            let raw = std::fs::read_to_string(&path).map_err(|e| {
+                Error::CalendarError(format!("Failed to read calendar {}: {}", path.display(), e))
+            })?;
+            let imported = import_ics(&raw, &ics.mapping)?;
+            calendar.terms.extend(imported.terms);
+            calendar.holidays.extend(imported.holidays);
+            calendar.staff_days.extend(imported.staff_days);
+        }
+
+        calendar.validate()?;
+        calendar.terms.sort_by_key(|t| t.start);
+        Ok(calendar)
+    }
+
+    /// Sorted by start.
+    pub fn terms(&self) -> &[DateRange] {
+        &self.terms
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (label, value) in [
+            ("holiday_label", &self.holiday_label),
+            ("staff_day_label", &self.staff_day_label),
+        ] {
+            if value.trim().is_empty() {
+                return Err(Error::CalendarError(format!("`calendar.{label}` must not be empty")));
+            }
+        }
+
+        let ranges = self.terms.iter().chain(&self.holidays).chain(&self.staff_days);
+        for range in ranges {
+            if range.end() < range.start {
+                return Err(Error::CalendarError(format!(
+                    "\"{}\" ends ({}) before it starts ({})",
+                    range.name,
+                    range.end(),
+                    range.start
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Staff days win over public holidays, which win over the term dates.
+    pub fn day_kind(&self, date: NaiveDate) -> DayKind<'_> {
+        if let Some(day) = self.staff_days.iter().find(|d| d.contains(date)) {
+            return DayKind::StaffDay {
+                label: &self.staff_day_label,
+                name:  &day.name,
+            };
+        }
+        if let Some(day) = self.holidays.iter().find(|d| d.contains(date)) {
+            return DayKind::Holiday {
+                label: &self.holiday_label,
+                name:  &day.name,
+            };
+        }
+        if !self.terms.is_empty() && !self.terms.iter().any(|t| t.contains(date)) {
+            return DayKind::Holiday {
+                label: &self.holiday_label,
+                name:  "School holidays",
+            };
+        }
+        DayKind::School
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn calendar(toml: &str) -> Result<TermCalendar> {
+        let def: CalendarDef = toml::from_str(toml).unwrap();
+        TermCalendar::build(&def, Path::to_path_buf)
+    }
+
+    const TERMS: &str = r#"
+        [[terms]]
+        name = "Term 1"
+        start = "2026-01-28"
+        end = "2026-04-02"
+
+        [[terms]]
+        name = "Term 2"
+        start = "2026-04-20"
+        end = "2026-06-26"
+
+        [[holidays]]
+        name = "Easter"
+        start = "2026-04-02"
+        end = "2026-04-06"
+
+        [[staff_days]]
+        name = "Moderation"
+        start = "2026-04-02"
+    "#;
+
+    #[test]
+    fn staff_days_win_over_holidays_which_win_over_being_outside_term() {
+        let calendar = calendar(TERMS).unwrap();
+        let kind = |day| calendar.day_kind(date(day));
+
+        assert_eq!(kind("2026-04-01"), DayKind::School);
+        // In term, on a holiday and on a staff day
+        assert_eq!(
+            kind("2026-04-02"),
+            DayKind::StaffDay {
+                label: "Staff Day",
+                name:  "Moderation",
+            }
+        );
+        // A named holiday keeps its name outside term too
+        assert_eq!(
+            kind("2026-04-03"),
+            DayKind::Holiday {
+                label: "Holiday",
+                name:  "Easter",
+            }
+        );
+        assert_eq!(
+            kind("2026-04-07"),
+            DayKind::Holiday {
+                label: "Holiday",
+                name:  "School holidays",
+            }
+        );
+        // Both ends of a term are inclusive
+        assert_eq!(kind("2026-04-20"), DayKind::School);
+        assert_eq!(kind("2026-06-26"), DayKind::School);
+        assert_eq!(calendar.terms()[0].name, "Term 1");
+    }
+
+    #[test]
+    fn without_terms_every_other_day_is_a_school_day() {
+        let calendar =
+            calendar("holiday_label = \"Closed\"\n[[holidays]]\nname = \"Show Day\"\nstart = \"2026-10-22\"")
+                .unwrap();
+        assert_eq!(calendar.day_kind(date("2026-12-25")), DayKind::School);
+        assert_eq!(
+            calendar.day_kind(date("2026-10-22")),
+            DayKind::Holiday {
+                label: "Closed",
+                name:  "Show Day",
+            }
+        );
+    }
+
+    #[test]
+    fn ranges_ending_before_they_start_are_rejected() {
+        let message = calendar("[[holidays]]\nname = \"Typo\"\nstart = \"2026-04-06\"\nend = \"2026-04-02\"")
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("\"Typo\" ends (2026-04-02) before it starts (2026-04-06)"), "{message}");
+        assert!(calendar("staff_day_label = \" \"").is_err());
+    }
+
+    #[test]
+    fn imported_dates_join_the_configured_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("school.ics"),
+            "BEGIN:VEVENT\r\nSUMMARY:Term 3\r\nDTSTART;VALUE=DATE:20260713\r\nDTEND;VALUE=DATE:20260919\r\nEND:VEVENT\r\n\
+             BEGIN:VEVENT\r\nSUMMARY:Term 1 Staff Day\r\nDTSTART;VALUE=DATE:20260127\r\nEND:VEVENT\r\n",
+        )
+        .unwrap();
+
+        let def: CalendarDef = toml::from_str(&format!("{TERMS}\n[ics]\npath = \"school.ics\"")).unwrap();
+        let calendar = TermCalendar::build(&def, |path| dir.path().join(path)).unwrap();
+
+        assert_eq!(
+            calendar
+                .terms()
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Term 1", "Term 2", "Term 3"]
+        );
+        assert_eq!(calendar.day_kind(date("2026-09-18")), DayKind::School);
+        assert!(matches!(calendar.day_kind(date("2026-09-19")), DayKind::Holiday { .. }));
+        assert!(matches!(calendar.day_kind(date("2026-01-27")), DayKind::StaffDay { .. }));
+    }
+}
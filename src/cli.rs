@@ -0,0 +1,196 @@
+//! Command line. Without a subcommand it logs the current logon, which is what the logon script /
+//! scheduled task has always run.
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+
+use crate::query::{Filter, OutputFormat};
+use crate::report::GroupBy;
+use crate::sink::LogFamily;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Logs who logs on where, and reads the logs back.")]
+pub struct Cli {
+    /// Config file to use instead of the usual lookup (`LOGON_LOGGER_CONFIG`, next to the exe, the share)
+    #[arg(long = "config", global = true, value_name = "PATH")]
+    pub config_path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Collect the current logon and write it to every sink (the default)
+    Log {
+        /// Also compact journals left in any older directory under the sinks' paths, which a
+        /// logon only looks for in the current and previous rotation period's directories
+        #[arg(long)]
+        sweep: bool,
+    },
+    /// Collect the current logon and print it, writing nothing
+    DryRun,
+    /// Find logons in the workbooks by user, computer, OU, period, date or serial
+    Query(QueryArgs),
+    /// Logon counts from the workbooks, grouped by day, period, computer, ...
+    Report(ReportArgs),
+    /// Merge workbooks into one, skipping entries it already has
+    Merge(MergeArgs),
+    /// Check the workbooks (and their journals) read back cleanly
+    Validate(ValidateArgs),
+    /// Zip old log files into monthly archives and delete old archives, see `[retention]`
+    Retention {
+        /// Only list what would be archived and deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Config file tools
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load and validate the config, then show where entries would go
+    Check,
+}
+
+/// Text filters are case-insensitive, `*` matches anything, eg: `--computer 'LIB-*'`.
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    #[arg(long, value_enum, default_value = "workstation")]
+    pub family:   LogFamily,
+    #[arg(long, short)]
+    pub user:     Option<String>,
+    #[arg(long, short)]
+    pub computer: Option<String>,
+    /// User OU, computer OU or the full OU path
+    #[arg(long)]
+    pub ou:       Option<String>,
+    /// eg: "Period 3"
+    #[arg(long, short)]
+    pub period:   Option<String>,
+    /// Serial number or hardware UUID
+    #[arg(long, short)]
+    pub serial:   Option<String>,
+    /// Only this day (YYYY-MM-DD)
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub on:       Option<NaiveDate>,
+    /// First day to include (YYYY-MM-DD)
+    #[arg(long)]
+    pub from:     Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD)
+    #[arg(long)]
+    pub to:       Option<NaiveDate>,
+    #[arg(long, short, value_enum, default_value = "table")]
+    pub format:   OutputFormat,
+}
+
+impl QueryArgs {
+    pub fn filter(&self) -> Filter {
+        Filter {
+            username: self.user.clone(),
+            computer: self.computer.clone(),
+            ou:       self.ou.clone(),
+            period:   self.period.clone(),
+            serial:   self.serial.clone(),
+            from:     self.on.or(self.from),
+            to:       self.on.or(self.to),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[arg(long, value_enum, default_value = "workstation")]
+    pub family: LogFamily,
+    /// First day to include (YYYY-MM-DD)
+    #[arg(long)]
+    pub from:   Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD)
+    #[arg(long)]
+    pub to:     Option<NaiveDate>,
+    #[arg(long, value_enum, default_value = "day")]
+    pub by:     GroupBy,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Layout of the inputs and the output
+    #[arg(long, value_enum, default_value = "workstation")]
+    pub family: LogFamily,
+    /// Workbook to merge into, created if it doesn't exist
+    #[arg(long, short)]
+    pub output: PathBuf,
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Only this family's workbooks, or the layout of the given `paths`
+    #[arg(long, value_enum)]
+    pub family: Option<LogFamily>,
+    /// Workbooks to check instead of the configured sinks' ones
+    pub paths:  Vec<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use clap::error::ErrorKind;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("logon_logger").chain(args.iter().copied()))
+    }
+
+    fn query(args: &[&str]) -> QueryArgs {
+        match parse(args).unwrap().command {
+            Some(Command::Query(query)) => query,
+            other => panic!("expected a query, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn no_arguments_log_the_current_logon() {
+        let cli = parse(&[]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(cli.config_path.is_none());
+    }
+
+    #[test]
+    fn config_is_accepted_after_the_subcommand() {
+        let cli = parse(&["config", "check", "--config", r"\\Server\Test\logon_logger.toml"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Config(ConfigCommand::Check))));
+        assert_eq!(cli.config_path, Some(PathBuf::from(r"\\Server\Test\logon_logger.toml")));
+    }
+
+    #[test]
+    fn on_is_one_day_and_conflicts_with_a_range() {
+        let filter = query(&["query", "--on", "2026-10-17"]).filter();
+        let day = NaiveDate::from_ymd_opt(2026, 10, 17);
+        assert_eq!((filter.from, filter.to), (day, day));
+
+        for range in ["--from", "--to"] {
+            let err = parse(&["query", "--on", "2026-10-17", range, "2026-10-01"]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict, "{range}");
+        }
+        let filter = query(&["query", "--from", "2026-10-01", "--to", "2026-10-17"]).filter();
+        assert_eq!((filter.from, filter.to), (NaiveDate::from_ymd_opt(2026, 10, 1), day));
+    }
+
+    #[test]
+    fn merge_needs_inputs() {
+        let err = parse(&["merge", "--output", "all.xlsx"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+    }
+}
@@ -1,7 +1,9 @@
 use chrono::{DateTime, Local};
+use serde::Deserialize;
 
 use crate::Result;
-use crate::executor::PsExecutor;
+use crate::directory::{DnResolver, UserRecord};
+use crate::dn::{DistinguishedName, OuConfig};
 
 #[derive(Debug, Clone)]
 pub struct BaseInfo {
@@ -11,6 +13,42 @@ pub struct BaseInfo {
     pub user_ou:       String,
     pub full_ou:       String,
     pub ws_ou:         String,
+    pub details:       UserDetails,
+}
+
+/// Extra user attributes from the directory, empty when unset or the lookup failed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserDetails {
+    pub department: String,
+    pub title:      String,
+    pub mail:       String,
+    /// Group names (the `CN` of each group DN), `; ` separated.
+    pub member_of:  String,
+}
+
+impl From<UserRecord> for UserDetails {
+    fn from(record: UserRecord) -> Self {
+        let member_of = record
+            .member_of
+            .iter()
+            .map(|group| {
+                group
+                    .parse::<DistinguishedName>()
+                    .ok()
+                    .and_then(|dn| dn.common_name().map(str::to_string))
+                    .unwrap_or_else(|| group.clone())
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        Self {
+            department: record.department.unwrap_or_default(),
+            title: record.title.unwrap_or_default(),
+            mail: record.mail.unwrap_or_default(),
+            member_of,
+        }
+    }
 }
 
 impl BaseInfo {
@@ -29,59 +67,88 @@ impl BaseInfo {
             user_ou,
             full_ou,
             ws_ou,
+            details: UserDetails::default(),
         }
     }
+
+    pub fn with_details(mut self, details: UserDetails) -> Self {
+        self.details = details;
+        self
+    }
 }
 
-pub async fn collect_base_info(executor: &PsExecutor) -> Result<BaseInfo> {
-    // TODO: [correctness] : We should be uppercasing these,
-    // left them as is due to original script. Consider uppercasing as a way to normalise the data.
-    let computer_name = std::env::var("COMPUTERNAME")
-        .map_err(|_| crate::Error::Generic("COMPUTERNAME env var not found".to_string()))?; // .to_uppercase();
+/// Who is logging on where, the names the directory lookups start from.
+#[derive(Debug, Clone)]
+pub struct Logon {
+    pub computer_name: String,
+    pub logon_name:    String,
+}
 
-    let logon_name = std::env::var("USERNAME")
-        .map_err(|_| crate::Error::Generic("USERNAME env var not found".to_string()))?; // .to_uppercase();
+impl Logon {
+    /// `COMPUTERNAME` and `USERNAME`, as the logon script sees them.
+    pub fn from_env() -> Result<Self> {
+        // TODO: [correctness] : We should be uppercasing these,
+        // left them as is due to original script. Consider uppercasing as a way to normalise the data.
+        let computer_name = std::env::var("COMPUTERNAME")
+            .map_err(|_| crate::Error::Generic("COMPUTERNAME env var not found".to_string()))?; // .to_uppercase();
 
-    let now = Local::now();
+        let logon_name = std::env::var("USERNAME")
+            .map_err(|_| crate::Error::Generic("USERNAME env var not found".to_string()))?; // .to_uppercase();
 
-    let user_dn_cmd =
-        format!("(Get-ADUser -Identity '{}' -Properties DistinguishedName).DistinguishedName", logon_name);
-    let comp_dn_cmd = format!(
-        "(Get-ADComputer -Identity '{}' -Properties DistinguishedName).DistinguishedName",
-        computer_name
-    );
+        Ok(Self {
+            computer_name,
+            logon_name,
+        })
+    }
+}
 
-    let (user_dn, comp_dn) = tokio::try_join!(executor.execute(user_dn_cmd), executor.execute(comp_dn_cmd))
-        .map_err(|e| crate::Error::Generic(format!("Failed to get DN: {}", e)))?;
+pub async fn collect_base_info(resolver: &dyn DnResolver, ou: &OuConfig, logon: &Logon) -> Result<BaseInfo> {
+    let Logon {
+        computer_name,
+        logon_name,
+    } = logon.clone();
+    let now = Local::now();
 
-    // HACK: [brittle] : This is _extremely_ brittle,
-    // if the structure of the output changes, this _WILL_ break.
-    // perhaps Regex? (though questionable...), parsing it properly
-    // would be much safer and resilient...
+    // A slow/unreachable directory shouldn't lose the logon, log it with "Unknown" OUs instead
+    let (user, comp_dn) = tokio::join!(
+        lookup("user", resolver.user(&logon_name)),
+        lookup("computer", resolver.computer_dn(&computer_name))
+    );
+    let comp_dn = comp_dn.and_then(|dn| parse_dn("computer", &dn));
+    let (user_dn, details) = match user {
+        Some(record) => (parse_dn("user", &record.dn), UserDetails::from(record)),
+        None => (None, UserDetails::default()),
+    };
 
     let username = user_dn
-        .split(',')
-        .next()
-        .and_then(|cn| cn.split('=').nth(1))
+        .as_ref()
+        .and_then(DistinguishedName::common_name)
         .unwrap_or(&logon_name)
         .to_string();
+    let user_ou = user_dn.as_ref().and_then(|dn| ou.user.extract(dn));
+    let full_ou = comp_dn.as_ref().and_then(|dn| ou.computer.full(dn));
+    let ws_ou = comp_dn.as_ref().and_then(|dn| ou.computer.extract(dn));
 
-    let user_ou = user_dn
-        .split(',')
-        .nth(1)
-        .and_then(|ou| ou.split('_').nth(1))
-        .unwrap_or("Unknown")
-        .to_string();
+    let [user_ou, full_ou, ws_ou] =
+        [user_ou, full_ou, ws_ou].map(|v| v.unwrap_or_else(|| "Unknown".to_string()));
 
-    let full_ou = comp_dn.split(',').nth(1).unwrap_or("Unknown").to_string();
+    Ok(BaseInfo::new(computer_name, username, now, user_ou, full_ou, ws_ou).with_details(details))
+}
 
-    // let ws_ou = full_ou.split('_').last().unwrap_or("Unknown").to_string();
-    let ws_ou = full_ou.split('_').next_back().unwrap_or("Unknown").to_string();
+async fn lookup<T>(kind: &str, lookup: impl Future<Output = Result<T>>) -> Option<T> {
+    lookup
+        .await
+        .inspect_err(|e| tracing::warn!("{} lookup failed, OUs will be logged as Unknown: {}", kind, e))
+        .ok()
+}
 
-    Ok(BaseInfo::new(computer_name, username, now, user_ou, full_ou, ws_ou))
+fn parse_dn(kind: &str, dn: &str) -> Option<DistinguishedName> {
+    dn.parse()
+        .inspect_err(|e| tracing::warn!("{} DN unusable, OUs will be logged as Unknown: {}", kind, e))
+        .ok()
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct HardwareInfo {
     pub make:           String,
     pub model:          String,
@@ -162,13 +229,16 @@ pub async fn collect_hardware() -> Result<HardwareInfo> {
     })
     .await?;
 
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(target_os = "linux")]
+    return tokio::task::spawn_blocking(linux::collect_hardware).await?;
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        unimplemented!("Hardware collection is only implemented for Windows");
+        unimplemented!("Hardware collection is only implemented for Windows and Linux");
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct OsInfo {
     pub os_version: String,
     pub os_name:    String,
@@ -194,8 +264,350 @@ pub async fn collect_os_info() -> Result<OsInfo> {
     })
     .await?;
 
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(target_os = "linux")]
+    return tokio::task::spawn_blocking(linux::collect_os_info).await?;
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        unimplemented!("OS info collection is only implemented for Windows");
+        unimplemented!("OS info collection is only implemented for Windows and Linux");
+    }
+}
+
+// Everything here is best-effort: DMI serial/UUID are root-only (0400) on most distros and thin clients
+// may have no DMI table at all, so unreadable values fall back (or end up empty) instead of failing the run.
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use super::{HardwareInfo, OsInfo};
+    use crate::Result;
+
+    // Relative to `ROOT`, so tests can point them at a temp dir
+    const ROOT: &str = "/";
+    const DMI_ID_DIR: &str = "sys/class/dmi/id";
+    const DEVICE_TREE_MODEL: &str = "sys/firmware/devicetree/base/model";
+    const MACHINE_ID: &str = "etc/machine-id";
+    const MACHINE_INFO: &str = "etc/machine-info";
+    const OS_RELEASE: [&str; 2] = ["etc/os-release", "usr/lib/os-release"];
+
+    pub(super) fn collect_hardware() -> Result<HardwareInfo> {
+        Ok(hardware_under(Path::new(ROOT)))
+    }
+
+    fn hardware_under(root: &Path) -> HardwareInfo {
+        let dmi = |name: &str| read_trimmed(&root.join(DMI_ID_DIR).join(name));
+
+        let make = dmi("sys_vendor")
+            .or_else(|| dmi("board_vendor"))
+            .unwrap_or_default();
+        let model = dmi("product_name")
+            // ARM boards have no DMI, the device tree carries the model instead (NUL terminated)
+            .or_else(|| {
+                read_trimmed(&root.join(DEVICE_TREE_MODEL)).map(|m| m.trim_end_matches('\0').to_string())
+            })
+            .unwrap_or_default();
+        // Without root, machine-id is the closest stable per-install identifier
+        let uuid = dmi("product_uuid")
+            .or_else(|| read_trimmed(&root.join(MACHINE_ID)))
+            .unwrap_or_default();
+        let serial_number = dmi("product_serial")
+            .or_else(|| dmi("board_serial"))
+            .unwrap_or_default();
+        let description = read_trimmed(&root.join(MACHINE_INFO))
+            .and_then(|raw| parse_env_file(&raw).remove("PRETTY_HOSTNAME"))
+            .unwrap_or_default();
+
+        HardwareInfo::new(make, model, uuid, serial_number, description)
+    }
+
+    pub(super) fn collect_os_info() -> Result<OsInfo> {
+        Ok(os_info_under(Path::new(ROOT)))
+    }
+
+    fn os_info_under(root: &Path) -> OsInfo {
+        let mut release = OS_RELEASE
+            .iter()
+            .find_map(|p| read_trimmed(&root.join(p)))
+            .map(|raw| parse_env_file(&raw))
+            .unwrap_or_default();
+
+        let os_name = release
+            .remove("NAME")
+            .or_else(|| release.remove("PRETTY_NAME"))
+            .unwrap_or_else(|| "Linux".to_string());
+        let os_version = release
+            .remove("VERSION_ID")
+            .or_else(|| release.remove("VERSION"))
+            .or_else(|| release.remove("BUILD_ID"))
+            .unwrap_or_default();
+
+        OsInfo::new(os_version, os_name)
+    }
+
+    /// `None` for unreadable, missing or blank files.
+    fn read_trimmed(path: &Path) -> Option<String> {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+
+    // os-release(5) / machine-info(5): shell-style KEY=value lines, values optionally quoted. As in the
+    // shell, single quotes are literal and backslash escapes only apply outside them.
+    fn parse_env_file(raw: &str) -> HashMap<String, String> {
+        raw.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.trim().to_string(), unquote(v.trim())))
+            .filter(|(_, v)| !v.is_empty())
+            .collect()
+    }
+
+    fn unquote(value: &str) -> String {
+        if let Some(literal) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            return literal.to_string();
+        }
+        let inner = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.extend(chars.next()),
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn write(root: &Path, path: &str, contents: &str) {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        fn dmi(root: &Path, name: &str, value: &str) {
+            write(root, &format!("{DMI_ID_DIR}/{name}"), &format!("{value}\n"));
+        }
+
+        #[test]
+        fn env_files_unquote_like_the_shell() {
+            let raw = r#"
+# comment
+NAME="Ubuntu"
+
+VERSION_ID='24.04'
+  PRETTY_NAME = "Ubuntu \"Noble\" 24.04"
+PRETTY_HOSTNAME='C:\Tools'
+ESCAPED=C:\\Tools
+DOUBLE="C:\\Tools"
+EMPTY=""
+NOT A PAIR
+"#;
+            let env = parse_env_file(raw);
+            assert_eq!(env["NAME"], "Ubuntu");
+            assert_eq!(env["VERSION_ID"], "24.04");
+            assert_eq!(env["PRETTY_NAME"], r#"Ubuntu "Noble" 24.04"#);
+            assert_eq!(env["PRETTY_HOSTNAME"], r"C:\Tools");
+            assert_eq!(env["ESCAPED"], r"C:\Tools");
+            assert_eq!(env["DOUBLE"], r"C:\Tools");
+            assert!(!env.contains_key("EMPTY"));
+            assert_eq!(env.len(), 6);
+        }
+
+        #[test]
+        fn dmi_values_win_when_readable() {
+            let root = tempfile::tempdir().unwrap();
+            let root = root.path();
+            dmi(root, "sys_vendor", "Dell Inc.");
+            dmi(root, "board_vendor", "Dell Inc. (board)");
+            dmi(root, "product_name", "OptiPlex 7010");
+            dmi(root, "product_uuid", "4C4C4544-0037-3610-8052-B4C04F4E3032");
+            dmi(root, "product_serial", "7RB2BG3");
+            write(root, DEVICE_TREE_MODEL, "Raspberry Pi 4 Model B\0");
+            write(root, MACHINE_ID, "0123456789abcdef0123456789abcdef\n");
+            write(root, MACHINE_INFO, "PRETTY_HOSTNAME='Library PC 14'\n");
+
+            let hardware = hardware_under(root);
+            assert_eq!(hardware.make, "Dell Inc.");
+            assert_eq!(hardware.model, "OptiPlex 7010");
+            assert_eq!(hardware.uuid, "4C4C4544-0037-3610-8052-B4C04F4E3032");
+            assert_eq!(hardware.serial_number, "7RB2BG3");
+            assert_eq!(hardware.os_description, "Library PC 14");
+        }
+
+        #[test]
+        fn missing_dmi_values_fall_back() {
+            let root = tempfile::tempdir().unwrap();
+            let root = root.path();
+            // Blank counts as missing, root-only files read as missing too
+            dmi(root, "sys_vendor", " ");
+            dmi(root, "board_vendor", "Raspberry Pi Foundation");
+            dmi(root, "board_serial", "10000000abcdef01");
+            write(root, DEVICE_TREE_MODEL, "Raspberry Pi 4 Model B\0");
+            write(root, MACHINE_ID, "0123456789abcdef0123456789abcdef\n");
+
+            let hardware = hardware_under(root);
+            assert_eq!(hardware.make, "Raspberry Pi Foundation");
+            assert_eq!(hardware.model, "Raspberry Pi 4 Model B");
+            assert_eq!(hardware.uuid, "0123456789abcdef0123456789abcdef");
+            assert_eq!(hardware.serial_number, "10000000abcdef01");
+            assert_eq!(hardware.os_description, "");
+        }
+
+        #[test]
+        fn nothing_readable_leaves_everything_empty() {
+            let root = tempfile::tempdir().unwrap();
+            let hardware = hardware_under(root.path());
+            assert_eq!(
+                [
+                    hardware.make,
+                    hardware.model,
+                    hardware.uuid,
+                    hardware.serial_number,
+                    hardware.os_description
+                ],
+                ["", "", "", "", ""].map(String::from)
+            );
+
+            let os = os_info_under(root.path());
+            assert_eq!((os.os_name.as_str(), os.os_version.as_str()), ("Linux", ""));
+        }
+
+        #[test]
+        fn os_release_falls_back_to_usr_lib() {
+            let root = tempfile::tempdir().unwrap();
+            write(
+                root.path(),
+                OS_RELEASE[1],
+                "PRETTY_NAME=\"Debian GNU/Linux 13\"\nVERSION=\"13 (trixie)\"\n",
+            );
+
+            let os = os_info_under(root.path());
+            assert_eq!(os.os_name, "Debian GNU/Linux 13");
+            assert_eq!(os.os_version, "13 (trixie)");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::executor::{ExecutorConfig, PsExecutor};
+    use crate::shell::{MockResponse, MockShell};
+
+    const USER_CMD: &str = "Get-ADUser -Identity 'jdoe' -Properties 'Department','Title','mail','MemberOf' | \
+                            Select-Object -Property 'DistinguishedName','Department','Title','mail','MemberOf' | \
+                            ConvertTo-Json -Compress";
+    const COMPUTER_CMD: &str = "Get-ADComputer -Identity 'LIB-PC-14' | Select-Object -Property \
+                                'DistinguishedName' | ConvertTo-Json -Compress";
+    const USER_JSON: &str = r#"{"DistinguishedName":"CN=Jane Doe,OU=Staff_Teachers,DC=school,DC=local","Department":"Science","Title":null,"mail":"jdoe@school.local","MemberOf":["CN=Teachers,OU=Groups,DC=school,DC=local","CN=Staff,OU=Groups,DC=school,DC=local"]}"#;
+    const COMPUTER_JSON: &str = r#"{"DistinguishedName":"CN=LIB-PC-14,OU=Computers_LIB,DC=school,DC=local"}"#;
+
+    fn reply(command: &str, stdout: &str, exit: i32) -> MockResponse {
+        MockResponse {
+            command: command.to_string(),
+            stdout: stdout.to_string(),
+            stderr: if exit == 0 {
+                String::new()
+            } else {
+                "lookup failed".to_string()
+            },
+            exit,
+            delay_ms: 0,
+        }
+    }
+
+    fn executor(responses: Vec<MockResponse>) -> Arc<PsExecutor> {
+        let config = ExecutorConfig {
+            retries: 0,
+            ..ExecutorConfig::default()
+        };
+        PsExecutor::new(Arc::new(MockShell::new(responses)), config)
+    }
+
+    fn logon(logon_name: &str) -> Logon {
+        Logon {
+            computer_name: "LIB-PC-14".to_string(),
+            logon_name:    logon_name.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fills_in_names_ous_and_details_from_the_directory() {
+        let resolver = executor(vec![
+            reply(USER_CMD, USER_JSON, 0),
+            reply(COMPUTER_CMD, COMPUTER_JSON, 0),
+        ]);
+
+        let info = collect_base_info(resolver.as_ref(), &OuConfig::default(), &logon("jdoe"))
+            .await
+            .unwrap();
+
+        assert_eq!(info.computer_name, "LIB-PC-14");
+        assert_eq!(info.username, "Jane Doe");
+        assert_eq!(info.user_ou, "Teachers");
+        assert_eq!(info.full_ou, "OU=Computers_LIB");
+        assert_eq!(info.ws_ou, "LIB");
+        assert_eq!(info.details.department, "Science");
+        assert_eq!(info.details.title, "");
+        assert_eq!(info.details.mail, "jdoe@school.local");
+        assert_eq!(info.details.member_of, "Teachers; Staff");
+    }
+
+    #[tokio::test]
+    async fn failed_user_lookup_logs_unknown_instead_of_failing() {
+        let resolver = executor(vec![reply(USER_CMD, "", 1), reply(COMPUTER_CMD, COMPUTER_JSON, 0)]);
+
+        let info = collect_base_info(resolver.as_ref(), &OuConfig::default(), &logon("jdoe"))
+            .await
+            .unwrap();
+
+        assert_eq!(info.username, "jdoe");
+        assert_eq!(info.user_ou, "Unknown");
+        assert_eq!(info.ws_ou, "LIB");
+        assert_eq!(info.details.member_of, "");
+    }
+
+    #[tokio::test]
+    async fn user_not_found_or_unparseable_dn_logs_unknown() {
+        let garbled = reply(&USER_CMD.replace("'jdoe'", "'ghost'"), r#"{"DistinguishedName":"not a dn"}"#, 0);
+        let resolver = executor(vec![reply(USER_CMD, "", 0), garbled, reply(COMPUTER_CMD, "", 0)]);
+
+        for name in ["jdoe", "ghost"] {
+            let info = collect_base_info(resolver.as_ref(), &OuConfig::default(), &logon(name))
+                .await
+                .unwrap();
+            assert_eq!(info.username, name);
+            assert_eq!(info.user_ou, "Unknown");
+            assert_eq!(info.full_ou, "Unknown");
+            assert_eq!(info.ws_ou, "Unknown");
+        }
+    }
+
+    #[tokio::test]
+    async fn logon_name_reaches_the_directory_as_one_literal() {
+        let user_cmd = USER_CMD.replace("'jdoe'", "'o''brien$(calc)'");
+        let resolver = executor(vec![
+            reply(&user_cmd, USER_JSON, 0),
+            reply(COMPUTER_CMD, COMPUTER_JSON, 0),
+        ]);
+
+        let info = collect_base_info(resolver.as_ref(), &OuConfig::default(), &logon("o'brien$(calc)"))
+            .await
+            .unwrap();
+
+        assert_eq!(info.username, "Jane Doe");
     }
 }
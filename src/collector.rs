@@ -0,0 +1,223 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Local;
+use serde::Deserialize;
+
+use crate::collect::{
+    BaseInfo,
+    HardwareInfo,
+    Logon,
+    OsInfo,
+    UserDetails,
+    collect_base_info,
+    collect_hardware,
+    collect_os_info,
+};
+use crate::config::Config;
+use crate::directory::DnResolver;
+use crate::dn::OuConfig;
+use crate::prelude::{Error, Result};
+
+/// A source for one of the collected info types (`BaseInfo`, `HardwareInfo`, `OsInfo`).
+#[async_trait]
+pub trait Collector<T>: Send + Sync {
+    async fn collect(&self) -> Result<T>;
+}
+
+pub type BoxedCollector<T> = Box<dyn Collector<T>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BaseCollectorKind {
+    /// DN lookups through the configured `[directory]` backend.
+    #[default]
+    ActiveDirectory,
+    Fixture,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SystemCollectorKind {
+    /// Whatever the current platform provides (WMI/registry on Windows).
+    #[default]
+    Native,
+    Fixture,
+}
+
+/// `[collectors]` config table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectorConfig {
+    pub base:     BaseCollectorKind,
+    pub hardware: SystemCollectorKind,
+    pub os:       SystemCollectorKind,
+    /// Required when any collector is `fixture`.
+    pub fixture:  Option<PathBuf>,
+}
+
+/// The set of collectors `main` runs, one per info type.
+pub struct Collectors {
+    pub base:     BoxedCollector<BaseInfo>,
+    pub hardware: BoxedCollector<HardwareInfo>,
+    pub os:       BoxedCollector<OsInfo>,
+}
+
+impl Collectors {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let collectors = &config.collectors;
+        let fixture = || -> Result<FixtureCollector> {
+            let path = collectors.fixture.as_ref().ok_or_else(|| {
+                Error::ConfigError(
+                    "`collectors.fixture` must be set when a collector uses the fixture".to_string(),
+                )
+            })?;
+            FixtureCollector::from_path(&config.resolve_relative(path))
+        };
+
+        let base: BoxedCollector<BaseInfo> = match collectors.base {
+            BaseCollectorKind::ActiveDirectory => {
+                Box::new(ActiveDirectoryCollector::new(
+                    config
+                        .directory
+                        .resolver(&config.executor, |p| config.resolve_relative(p))?,
+                    config.ou.clone(),
+                ))
+            }
+            BaseCollectorKind::Fixture => Box::new(fixture()?),
+        };
+        let hardware: BoxedCollector<HardwareInfo> = match collectors.hardware {
+            SystemCollectorKind::Native => Box::new(NativeCollector),
+            SystemCollectorKind::Fixture => Box::new(fixture()?),
+        };
+        let os: BoxedCollector<OsInfo> = match collectors.os {
+            SystemCollectorKind::Native => Box::new(NativeCollector),
+            SystemCollectorKind::Fixture => Box::new(fixture()?),
+        };
+
+        Ok(Self { base, hardware, os })
+    }
+
+    pub async fn collect(&self) -> Result<(BaseInfo, HardwareInfo, OsInfo)> {
+        tokio::try_join!(self.base.collect(), self.hardware.collect(), self.os.collect())
+    }
+}
+
+pub struct ActiveDirectoryCollector {
+    resolver: Arc<dyn DnResolver>,
+    ou:       OuConfig,
+}
+
+impl ActiveDirectoryCollector {
+    pub fn new(resolver: Arc<dyn DnResolver>, ou: OuConfig) -> Self {
+        Self { resolver, ou }
+    }
+}
+
+#[async_trait]
+impl Collector<BaseInfo> for ActiveDirectoryCollector {
+    async fn collect(&self) -> Result<BaseInfo> {
+        collect_base_info(self.resolver.as_ref(), &self.ou, &Logon::from_env()?).await
+    }
+}
+
+/// Platform-native hardware and OS collection.
+pub struct NativeCollector;
+
+#[async_trait]
+impl Collector<HardwareInfo> for NativeCollector {
+    async fn collect(&self) -> Result<HardwareInfo> {
+        collect_hardware().await
+    }
+}
+
+#[async_trait]
+impl Collector<OsInfo> for NativeCollector {
+    async fn collect(&self) -> Result<OsInfo> {
+        collect_os_info().await
+    }
+}
+
+/// Canned values read from a TOML file, lets the whole pipeline run without AD, WMI or a registry.
+#[derive(Debug, Clone)]
+pub struct FixtureCollector {
+    data: Arc<FixtureData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixtureData {
+    base:     BaseFixture,
+    hardware: HardwareInfo,
+    os:       OsInfo,
+}
+
+// `BaseInfo` minus `now`, which is always taken at collection time.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BaseFixture {
+    computer_name: String,
+    username:      String,
+    user_ou:       String,
+    full_ou:       String,
+    ws_ou:         String,
+    #[serde(default)]
+    department:    String,
+    #[serde(default)]
+    title:         String,
+    #[serde(default)]
+    mail:          String,
+    #[serde(default)]
+    member_of:     String,
+}
+
+impl FixtureCollector {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            Error::ConfigError(format!("Failed to read collector fixture {}: {}", path.display(), e))
+        })?;
+        let data: FixtureData = toml::from_str(&raw).map_err(|source| {
+            Error::ConfigParseError {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?;
+        Ok(Self { data: Arc::new(data) })
+    }
+}
+
+#[async_trait]
+impl Collector<BaseInfo> for FixtureCollector {
+    async fn collect(&self) -> Result<BaseInfo> {
+        let base = &self.data.base;
+        Ok(BaseInfo::new(
+            base.computer_name.clone(),
+            base.username.clone(),
+            Local::now(),
+            base.user_ou.clone(),
+            base.full_ou.clone(),
+            base.ws_ou.clone(),
+        )
+        .with_details(UserDetails {
+            department: base.department.clone(),
+            title:      base.title.clone(),
+            mail:       base.mail.clone(),
+            member_of:  base.member_of.clone(),
+        }))
+    }
+}
+
+#[async_trait]
+impl Collector<HardwareInfo> for FixtureCollector {
+    async fn collect(&self) -> Result<HardwareInfo> {
+        Ok(self.data.hardware.clone())
+    }
+}
+
+#[async_trait]
+impl Collector<OsInfo> for FixtureCollector {
+    async fn collect(&self) -> Result<OsInfo> {
+        Ok(self.data.os.clone())
+    }
+}
@@ -0,0 +1,477 @@
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer};
+
+use crate::calendar::{CalendarDef, TermCalendar};
+use crate::collector::CollectorConfig;
+use crate::directory::DirectoryConfig;
+use crate::dn::OuConfig;
+use crate::executor::ExecutorConfig;
+use crate::lock::LockConfig;
+use crate::period::{Schedule, ScheduleDef, Timetable};
+use crate::prelude::{Error, Result, current_path};
+use crate::retention::RetentionConfig;
+use crate::sink::SinkConfig;
+use crate::spool::SpoolConfig;
+
+// Compiled-in fallbacks, used when neither a config file nor an env var provides a value.
+pub const DEFAULT_WORKSHEET_NAME: &str = "Logons";
+pub const DEFAULT_WS_BASE_PATH: &str = r"\\Server\LogonLogger$\Logs\ComputerNEW";
+pub const DEFAULT_USER_BASE_PATH: &str = r"\\Server\LogonLogger$\Logs\UserNEW";
+
+/// Name of the config file looked up next to the executable.
+pub const CONFIG_FILE_NAME: &str = "logon_logger.toml";
+
+/// Shared (network) config location, tried after the exe-local one.
+pub const DEFAULT_SHARED_CONFIG_PATH: &str = r"\\Server\LogonLogger$\logon_logger.toml";
+
+/// Env var pointing at an explicit config file (local or UNC path).
+pub const CONFIG_PATH_ENV: &str = "LOGON_LOGGER_CONFIG";
+
+pub const WORKSHEET_NAME_ENV: &str = "LOGON_LOGGER_WORKSHEET_NAME";
+pub const WS_BASE_PATH_ENV: &str = "LOGON_LOGGER_WS_BASE_PATH";
+pub const USER_BASE_PATH_ENV: &str = "LOGON_LOGGER_USER_BASE_PATH";
+pub const TIMETABLE_PATH_ENV: &str = "LOGON_LOGGER_TIMETABLE";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub worksheet_name: String,
+    pub ws_base_path:   String,
+    pub user_base_path: String,
+
+    /// Inline `[[timetable.periods]]`, replaced by `timetable_path` when that is set.
+    pub timetable:      Timetable,
+    /// Standalone timetable file (`.toml` or `.json`), relative paths resolve against the config file.
+    pub timetable_path: Option<PathBuf>,
+    /// Weekday variants and week A/B rotation on top of `timetable`.
+    #[serde(rename = "schedule")]
+    pub schedule_def:   ScheduleDef,
+    /// Term dates, public holidays and staff days, optionally imported from an `.ics` export.
+    #[serde(rename = "calendar")]
+    pub calendar_def:   CalendarDef,
+
+    /// Which implementation gathers each part of the entry.
+    pub collectors: CollectorConfig,
+    /// Where the AD lookups for the `active-directory` base collector go.
+    pub directory:  DirectoryConfig,
+    /// Which OU of the user/computer DN feeds the `*_ou` columns.
+    pub ou:         OuConfig,
+    /// Timeout/retry policy for PowerShell commands.
+    pub executor:   ExecutorConfig,
+    /// Where entries go, empty means the workstation + user workbooks under the base paths.
+    pub sinks:      Vec<SinkConfig>,
+    /// Lock files guarding workbooks shared between machines.
+    pub lock:       LockConfig,
+    /// Local queue for entries that couldn't be delivered.
+    pub spool:      SpoolConfig,
+    /// Archiving and deleting old log files, see `logon_logger retention`.
+    pub retention:  RetentionConfig,
+
+    /// Built from `timetable` + `schedule_def` during [`Config::load`].
+    #[serde(skip)]
+    pub schedule: Schedule,
+
+    /// Where this config was loaded from, `None` when running purely on the fallbacks.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            worksheet_name: DEFAULT_WORKSHEET_NAME.to_string(),
+            ws_base_path:   DEFAULT_WS_BASE_PATH.to_string(),
+            user_base_path: DEFAULT_USER_BASE_PATH.to_string(),
+            timetable:      Timetable::default(),
+            timetable_path: None,
+            schedule_def:   ScheduleDef::default(),
+            calendar_def:   CalendarDef::default(),
+            collectors:     CollectorConfig::default(),
+            directory:      DirectoryConfig::default(),
+            ou:             OuConfig::default(),
+            executor:       ExecutorConfig::default(),
+            sinks:          vec![],
+            lock:           LockConfig::default(),
+            spool:          SpoolConfig::default(),
+            retention:      RetentionConfig::default(),
+            schedule:       Schedule::default(),
+            source:         None,
+        }
+    }
+}
+
+impl Config {
+    /// Resolves the config file, applies env var overrides on top and validates the result.
+    ///
+    /// Lookup order:
+    /// 1. `explicit`, from `--config` (must exist)
+    /// 2. `LOGON_LOGGER_CONFIG` (must exist if set)
+    /// 3. `logon_logger.toml` next to the executable
+    /// 4. `\\Server\LogonLogger$\logon_logger.toml`
+    /// 5. compiled-in fallbacks
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        let mut config = match Self::locate(explicit)? {
+            Some(path) => Self::from_path(&path)?,
+            None => Self::default(),
+        };
+
+        config.apply_env_overrides()?;
+        config.load_schedule()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads `toml` as the config file `<dir>/logon_logger.toml`, env overrides left out.
+    #[cfg(test)]
+    pub fn for_test(dir: &Path, toml: &str) -> Result<Self> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        std::fs::write(&path, toml)?;
+        let mut config = Self::from_path(&path)?;
+        config.load_schedule()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            Error::ConfigError(format!("Failed to read config file {}: {}", path.display(), e))
+        })?;
+
+        let mut config: Config = toml::from_str(&raw).map_err(|source| {
+            Error::ConfigParseError {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?;
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    fn locate(explicit: Option<&Path>) -> Result<Option<PathBuf>> {
+        Self::locate_from(explicit, env_override(CONFIG_PATH_ENV)?, &current_path()?)
+    }
+
+    // `locate` with the env var and the executable's directory passed in.
+    fn locate_from(
+        explicit: Option<&Path>,
+        env_path: Option<String>,
+        exe_dir: &Path,
+    ) -> Result<Option<PathBuf>> {
+        if let Some(path) = explicit {
+            if !path.is_file() {
+                return Err(Error::ConfigError(format!("Config file {} does not exist", path.display())));
+            }
+            return Ok(Some(path.to_path_buf()));
+        }
+
+        if let Some(env_path) = env_path {
+            let path = PathBuf::from(env_path);
+            if !path.is_file() {
+                return Err(Error::ConfigError(format!(
+                    "{} points to {}, which does not exist",
+                    CONFIG_PATH_ENV,
+                    path.display()
+                )));
+            }
+            return Ok(Some(path));
+        }
+
+        let local = exe_dir.join(CONFIG_FILE_NAME);
+        if local.is_file() {
+            return Ok(Some(local));
+        }
+
+        let shared = PathBuf::from(DEFAULT_SHARED_CONFIG_PATH);
+        if shared.is_file() {
+            return Ok(Some(shared));
+        }
+
+        Ok(None)
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<()> {
+        self.apply_overrides(env_override)
+    }
+
+    // `apply_env_overrides` with the lookup passed in.
+    fn apply_overrides(&mut self, var: impl Fn(&str) -> Result<Option<String>>) -> Result<()> {
+        if let Some(v) = var(WORKSHEET_NAME_ENV)? {
+            self.worksheet_name = v;
+        }
+        if let Some(v) = var(WS_BASE_PATH_ENV)? {
+            self.ws_base_path = v;
+        }
+        if let Some(v) = var(USER_BASE_PATH_ENV)? {
+            self.user_base_path = v;
+        }
+        if let Some(v) = var(TIMETABLE_PATH_ENV)? {
+            self.timetable_path = Some(PathBuf::from(v));
+        }
+        Ok(())
+    }
+
+    fn load_schedule(&mut self) -> Result<()> {
+        if let Some(path) = &self.timetable_path {
+            let path = self.resolve_relative(path);
+            self.timetable = Timetable::from_path(&path)?;
+        }
+        let calendar = TermCalendar::build(&self.calendar_def, |p| self.resolve_relative(p))?;
+        self.schedule = Schedule::build(self.timetable.clone(), &self.schedule_def, calendar, |p| {
+            self.resolve_relative(p)
+        })?;
+        Ok(())
+    }
+
+    /// Relative paths in the config are relative to the config file, not the working dir.
+    pub fn resolve_relative(&self, path: &Path) -> PathBuf {
+        match self.source.as_deref().and_then(Path::parent) {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_worksheet_name(&self.worksheet_name)?;
+
+        for (key, value) in [
+            ("ws_base_path", &self.ws_base_path),
+            ("user_base_path", &self.user_base_path),
+        ] {
+            if value.trim().is_empty() {
+                return Err(Error::ConfigError(format!("`{key}` must not be empty")));
+            }
+        }
+
+        if self.executor.timeout_secs == 0 {
+            return Err(Error::ConfigError("`executor.timeout_secs` must be at least 1".to_string()));
+        }
+        if self.executor.pool_size == 0 {
+            return Err(Error::ConfigError("`executor.pool_size` must be at least 1".to_string()));
+        }
+
+        if self.lock.stale_secs == 0 {
+            return Err(Error::ConfigError("`lock.stale_secs` must be at least 1".to_string()));
+        }
+        self.retention.validate()?;
+        for sink in &self.sinks {
+            sink.validate(self)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `Some` only for set, non-empty vars. Non-unicode values are an error rather than ignored.
+fn env_override(name: &str) -> Result<Option<String>> {
+    non_empty(std::env::var(name))
+}
+
+fn non_empty(value: std::result::Result<String, std::env::VarError>) -> Result<Option<String>> {
+    match value {
+        Ok(v) if v.trim().is_empty() => Ok(None),
+        Ok(v) => Ok(Some(v)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Excel's own rules, rust_xlsxwriter would otherwise only tell us at save time.
+fn validate_worksheet_name(name: &str) -> Result<()> {
+    const FORBIDDEN: &[char] = &['[', ']', ':', '*', '?', '/', '\\'];
+
+    let len = name.chars().count();
+    if len == 0 || len > 31 {
+        return Err(Error::ConfigError(format!(
+            "`worksheet_name` must be 1-31 characters long, got {len} (\"{name}\")"
+        )));
+    }
+    if let Some(c) = name.chars().find(|c| FORBIDDEN.contains(c)) {
+        return Err(Error::ConfigError(format!(
+            "`worksheet_name` \"{name}\" contains the forbidden character '{c}'"
+        )));
+    }
+    if name.starts_with('\'') || name.ends_with('\'') {
+        return Err(Error::ConfigError(format!(
+            "`worksheet_name` \"{name}\" must not start or end with an apostrophe"
+        )));
+    }
+    Ok(())
+}
+
+/// Accepts TOML's native dates (`start = 2026-01-28`) as well as quoted `"2026-01-28"` strings.
+pub(crate) fn deserialize_date<'de, D>(deserializer: D) -> std::result::Result<NaiveDate, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDate {
+        Toml(toml::value::Datetime),
+        Text(String),
+    }
+
+    let text = match RawDate::deserialize(deserializer)? {
+        RawDate::Toml(dt) => dt.to_string(),
+        RawDate::Text(text) => text,
+    };
+    NaiveDate::parse_from_str(&text, "%Y-%m-%d").map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn deserialize_opt_date<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<NaiveDate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_date(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    // Stands in for the environment, through the same empty value handling
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Result<Option<String>> {
+        let vars: HashMap<String, String> =
+            vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| non_empty(vars.get(name).cloned().ok_or(std::env::VarError::NotPresent))
+    }
+
+    fn config_error(result: Result<impl std::fmt::Debug>) -> String {
+        match result {
+            Err(Error::ConfigError(message)) => message,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn explicit_path_beats_the_env_var_beats_the_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe_dir = dir.path().join("bin");
+        std::fs::create_dir(&exe_dir).unwrap();
+        let local = exe_dir.join(CONFIG_FILE_NAME);
+        let from_env = dir.path().join("env.toml");
+        let explicit = dir.path().join("explicit.toml");
+        for path in [&local, &from_env, &explicit] {
+            std::fs::write(path, "").unwrap();
+        }
+        let env_path = || Some(from_env.display().to_string());
+
+        let found = Config::locate_from(Some(&explicit), env_path(), &exe_dir).unwrap();
+        assert_eq!(found, Some(explicit.clone()));
+        let found = Config::locate_from(None, env_path(), &exe_dir).unwrap();
+        assert_eq!(found, Some(from_env.clone()));
+        let found = Config::locate_from(None, None, &exe_dir).unwrap();
+        assert_eq!(found, Some(local));
+        assert_eq!(Config::locate_from(None, None, dir.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_explicit_or_env_path_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(CONFIG_FILE_NAME), "").unwrap();
+        let missing = dir.path().join("missing.toml");
+
+        let message = config_error(Config::locate_from(Some(&missing), None, dir.path()));
+        assert!(message.contains("missing.toml"), "{message}");
+        let message =
+            config_error(Config::locate_from(None, Some(missing.display().to_string()), dir.path()));
+        assert!(message.contains(CONFIG_PATH_ENV), "{message}");
+    }
+
+    #[test]
+    fn empty_env_vars_are_ignored() {
+        assert_eq!(non_empty(Ok(" ".to_string())).unwrap(), None);
+        assert_eq!(non_empty(Err(std::env::VarError::NotPresent)).unwrap(), None);
+        assert!(non_empty(Err(std::env::VarError::NotUnicode("\u{fffd}".into()))).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(CONFIG_FILE_NAME), "").unwrap();
+        let found = Config::locate_from(None, non_empty(Ok(String::new())).unwrap(), dir.path()).unwrap();
+        assert_eq!(found, Some(dir.path().join(CONFIG_FILE_NAME)));
+
+        let mut config = Config::for_test(dir.path(), "worksheet_name = \"FromFile\"").unwrap();
+        config.apply_overrides(env(&[(WORKSHEET_NAME_ENV, "")])).unwrap();
+        assert_eq!(config.worksheet_name, "FromFile");
+    }
+
+    #[test]
+    fn env_values_override_file_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = r#"
+            worksheet_name = "FromFile"
+            ws_base_path = "ws-from-file"
+            user_base_path = "users-from-file"
+        "#;
+        let mut config = Config::for_test(dir.path(), toml).unwrap();
+        config
+            .apply_overrides(env(&[
+                (WORKSHEET_NAME_ENV, "FromEnv"),
+                (WS_BASE_PATH_ENV, r"\\Other\Logs"),
+                (TIMETABLE_PATH_ENV, "bells.toml"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.worksheet_name, "FromEnv");
+        assert_eq!(config.ws_base_path, r"\\Other\Logs");
+        assert_eq!(config.user_base_path, "users-from-file");
+        assert_eq!(config.timetable_path, Some(PathBuf::from("bells.toml")));
+    }
+
+    #[test]
+    fn worksheet_names_follow_excels_rules() {
+        assert!(validate_worksheet_name("Logons").is_ok());
+        assert!(validate_worksheet_name("Log'ons").is_ok());
+        assert!(validate_worksheet_name(&"x".repeat(31)).is_ok());
+        // Characters, not bytes
+        assert!(validate_worksheet_name(&"é".repeat(31)).is_ok());
+
+        assert!(config_error(validate_worksheet_name("")).contains("got 0"));
+        assert!(config_error(validate_worksheet_name(&"x".repeat(32))).contains("got 32"));
+        for c in ['[', ']', ':', '*', '?', '/', '\\'] {
+            let message = config_error(validate_worksheet_name(&format!("Logons{c}2026")));
+            assert!(message.contains(&format!("'{c}'")), "{message}");
+        }
+        assert!(config_error(validate_worksheet_name("'Logons")).contains("apostrophe"));
+        assert!(config_error(validate_worksheet_name("Logons'")).contains("apostrophe"));
+    }
+
+    #[test]
+    fn zero_timeouts_pools_and_stale_ages_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        for (toml, key) in [
+            ("[executor]\ntimeout_secs = 0", "executor.timeout_secs"),
+            ("[executor]\npool_size = 0", "executor.pool_size"),
+            ("[lock]\nstale_secs = 0", "lock.stale_secs"),
+        ] {
+            let message = config_error(Config::for_test(dir.path(), toml));
+            assert!(message.contains(key), "{message}");
+        }
+        assert!(Config::for_test(dir.path(), "[executor]\ntimeout_secs = 1\npool_size = 1").is_ok());
+    }
+
+    #[test]
+    fn dates_are_toml_dates_or_strings() {
+        #[derive(Debug, Deserialize)]
+        struct Dated {
+            #[serde(deserialize_with = "deserialize_date")]
+            day:   NaiveDate,
+            #[serde(default, deserialize_with = "deserialize_opt_date")]
+            until: Option<NaiveDate>,
+        }
+        let expected = NaiveDate::from_ymd_opt(2026, 1, 28).unwrap();
+
+        let dated: Dated = toml::from_str("day = 2026-01-28").unwrap();
+        assert_eq!((dated.day, dated.until), (expected, None));
+        let dated: Dated = toml::from_str("day = \"2026-01-28\"\nuntil = 2026-01-28").unwrap();
+        assert_eq!((dated.day, dated.until), (expected, Some(expected)));
+
+        assert!(toml::from_str::<Dated>("day = \"28/01/2026\"").is_err());
+        assert!(toml::from_str::<Dated>("day = 2026-01-28T08:40:00").is_err());
+        assert!(toml::from_str::<Dated>("day = 20260128").is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry, ldap_escape};
+use serde::{Deserialize, Deserializer};
+use tokio::sync::OnceCell;
+
+use crate::executor::{ExecutorConfig, PsCommand, PsExecutor};
+use crate::prelude::{Error, Result};
+
+/// Looks up the directory entries `collect_base_info` derives the OUs and user details from.
+#[async_trait]
+pub trait DnResolver: Send + Sync {
+    async fn user(&self, username: &str) -> Result<UserRecord>;
+
+    async fn computer_dn(&self, computer_name: &str) -> Result<String>;
+}
+
+/// The user attributes we log, named as `Get-ADUser` returns them.
+pub const USER_ATTRIBUTES: [&str; 4] = ["Department", "Title", "mail", "MemberOf"];
+
+/// The user's DN plus `USER_ATTRIBUTES`, any of which may be unset in the directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UserRecord {
+    #[serde(rename = "DistinguishedName")]
+    pub dn:         String,
+    #[serde(rename = "Department", default)]
+    pub department: Option<String>,
+    #[serde(rename = "Title", default)]
+    pub title:      Option<String>,
+    #[serde(rename = "mail", alias = "EmailAddress", default)]
+    pub mail:       Option<String>,
+    /// Group DNs.
+    #[serde(rename = "MemberOf", default, deserialize_with = "one_or_many")]
+    pub member_of:  Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComputerRecord {
+    #[serde(rename = "DistinguishedName")]
+    dn: String,
+}
+
+// `ConvertTo-Json` writes a single-valued collection as a bare string on some PowerShell versions.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        None => vec![],
+        Some(OneOrMany::One(v)) => vec![v],
+        Some(OneOrMany::Many(v)) => v,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirectoryBackend {
+    /// `Get-ADUser` / `Get-ADComputer`, needs RSAT on every client.
+    #[default]
+    Powershell,
+    Ldap,
+}
+
+/// `[directory]` config table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectoryConfig {
+    pub backend: DirectoryBackend,
+    pub ldap:    Option<LdapConfig>,
+}
+
+impl DirectoryConfig {
+    /// `resolve` maps the shell mock path to a real path (the config resolves it relative to itself).
+    pub fn resolver(
+        &self,
+        executor: &ExecutorConfig,
+        resolve: impl Fn(&Path) -> PathBuf,
+    ) -> Result<Arc<dyn DnResolver>> {
+        match self.backend {
+            DirectoryBackend::Powershell => {
+                let mock = executor.mock.as_deref().map(resolve);
+                let shell = executor.shell.backend(mock.as_deref())?;
+                Ok(PsExecutor::new(shell, executor.clone()))
+            }
+            DirectoryBackend::Ldap => {
+                let ldap = self.ldap.clone().ok_or_else(|| {
+                    Error::ConfigError(
+                        "`directory.backend = \"ldap\"` needs a `[directory.ldap]` table".to_string(),
+                    )
+                })?;
+                ldap.validate()?;
+                Ok(Arc::new(LdapResolver::new(ldap)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LdapBind {
+    Anonymous,
+    /// `bind_dn` + the password from `password_env`.
+    #[default]
+    Simple,
+    /// Kerberos as the logged on user/computer, requires the `gssapi` cargo feature.
+    Gssapi,
+}
+
+pub const DEFAULT_LDAP_PASSWORD_ENV: &str = "LOGON_LOGGER_LDAP_PASSWORD";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LdapConfig {
+    /// eg: `ldaps://dc01.school.local` or `ldap://localhost:3893` for a glauth stand-in.
+    pub url:             String,
+    pub base_dn:         String,
+    pub bind:            LdapBind,
+    pub bind_dn:         Option<String>,
+    /// Name of the env var holding the bind password, never the password itself.
+    pub password_env:    String,
+    pub starttls:        bool,
+    pub no_tls_verify:   bool,
+    pub timeout_secs:    u64,
+    /// `{name}` is replaced with the (escaped) logon name.
+    pub user_filter:     String,
+    /// `{name}` is replaced with the (escaped) computer name.
+    pub computer_filter: String,
+}
+
+impl Default for LdapConfig {
+    fn default() -> Self {
+        Self {
+            url:             String::new(),
+            base_dn:         String::new(),
+            bind:            LdapBind::default(),
+            bind_dn:         None,
+            password_env:    DEFAULT_LDAP_PASSWORD_ENV.to_string(),
+            starttls:        false,
+            no_tls_verify:   false,
+            timeout_secs:    10,
+            user_filter:     "(&(objectCategory=person)(objectClass=user)(sAMAccountName={name}))"
+                .to_string(),
+            computer_filter: "(&(objectClass=computer)(sAMAccountName={name}$))".to_string(),
+        }
+    }
+}
+
+impl LdapConfig {
+    fn validate(&self) -> Result<()> {
+        if self.url.trim().is_empty() {
+            return Err(Error::ConfigError("`directory.ldap.url` must be set".to_string()));
+        }
+        if self.base_dn.trim().is_empty() {
+            return Err(Error::ConfigError("`directory.ldap.base_dn` must be set".to_string()));
+        }
+        if self.bind == LdapBind::Simple && self.bind_dn.is_none() {
+            return Err(Error::ConfigError("`directory.ldap.bind = \"simple\"` needs `bind_dn`".to_string()));
+        }
+        for (key, filter) in [
+            ("user_filter", &self.user_filter),
+            ("computer_filter", &self.computer_filter),
+        ] {
+            if !filter.contains("{name}") {
+                return Err(Error::ConfigError(format!(
+                    "`directory.ldap.{key}` must contain a `{{name}}` placeholder"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Host part of the URL, GSSAPI needs it to build the `ldap/<host>` service principal.
+    #[cfg_attr(not(feature = "gssapi"), allow(dead_code))]
+    fn host(&self) -> &str {
+        let rest = self.url.split_once("://").map_or(self.url.as_str(), |(_, r)| r);
+        let authority = rest.split('/').next().unwrap_or(rest);
+        // `[::1]:389`, the literal's own colons aren't the port's
+        if let Some(literal) = authority.strip_prefix('[') {
+            return literal.split(']').next().unwrap_or(literal);
+        }
+        authority.split(':').next().unwrap_or(authority)
+    }
+}
+
+/// Native LDAP lookups, one bound connection shared by every query of the run.
+pub struct LdapResolver {
+    config: LdapConfig,
+    conn:   OnceCell<Ldap>,
+}
+
+impl LdapResolver {
+    pub fn new(config: LdapConfig) -> Self {
+        Self {
+            config,
+            conn: OnceCell::new(),
+        }
+    }
+
+    async fn connection(&self) -> Result<Ldap> {
+        let ldap = self.conn.get_or_try_init(|| self.connect()).await?;
+        Ok(ldap.clone())
+    }
+
+    async fn connect(&self) -> Result<Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(self.timeout())
+            .set_starttls(self.config.starttls)
+            .set_no_tls_verify(self.config.no_tls_verify);
+
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.url).await?;
+        ldap3::drive!(conn);
+
+        match self.config.bind {
+            LdapBind::Anonymous => {}
+            LdapBind::Simple => {
+                let bind_dn = self.config.bind_dn.as_deref().unwrap_or_default();
+                let password = std::env::var(&self.config.password_env).map_err(|_| {
+                    Error::ConfigError(format!(
+                        "LDAP simple bind needs the password in the `{}` env var",
+                        self.config.password_env
+                    ))
+                })?;
+                ldap.with_timeout(self.timeout())
+                    .simple_bind(bind_dn, &password)
+                    .await?
+                    .success()?;
+            }
+            #[cfg(feature = "gssapi")]
+            LdapBind::Gssapi => {
+                ldap.with_timeout(self.timeout())
+                    .sasl_gssapi_bind(self.config.host())
+                    .await?
+                    .success()?;
+            }
+            #[cfg(not(feature = "gssapi"))]
+            LdapBind::Gssapi => {
+                return Err(Error::ConfigError(
+                    "`directory.ldap.bind = \"gssapi\"` needs a build with the `gssapi` feature".to_string(),
+                ));
+            }
+        }
+
+        Ok(ldap)
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config.timeout_secs)
+    }
+
+    async fn find_one(&self, filter_template: &str, name: &str, attrs: Vec<&str>) -> Result<SearchEntry> {
+        let filter = search_filter(filter_template, name);
+        let mut ldap = self.connection().await?;
+
+        let (entries, _) = ldap
+            .with_timeout(self.timeout())
+            .search(&self.config.base_dn, Scope::Subtree, &filter, attrs)
+            .await?
+            .success()?;
+
+        let mut found = entries.into_iter().map(SearchEntry::construct);
+        match (found.next(), found.next()) {
+            (Some(entry), None) => Ok(entry),
+            (None, _) => Err(Error::DirectoryError(format!("No directory entry matches {filter}"))),
+            (Some(_), Some(_)) => {
+                Err(Error::DirectoryError(format!("More than one directory entry matches {filter}")))
+            }
+        }
+    }
+}
+
+// `template` with `{name}` replaced by `name`, its filter metacharacters escaped (RFC 4515).
+fn search_filter(template: &str, name: &str) -> String {
+    template.replace("{name}", &ldap_escape(name))
+}
+
+#[async_trait]
+impl DnResolver for LdapResolver {
+    async fn user(&self, username: &str) -> Result<UserRecord> {
+        let entry = self
+            .find_one(&self.config.user_filter, username, USER_ATTRIBUTES.to_vec())
+            .await?;
+        // Servers don't all echo the attribute names back in the case they were asked for
+        let values = |name: &str| {
+            entry
+                .attrs
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        let first = |name: &str| values(name).into_iter().next();
+
+        Ok(UserRecord {
+            department: first("department"),
+            title:      first("title"),
+            mail:       first("mail"),
+            member_of:  values("memberOf"),
+            dn:         entry.dn.clone(),
+        })
+    }
+
+    async fn computer_dn(&self, computer_name: &str) -> Result<String> {
+        let entry = self
+            .find_one(&self.config.computer_filter, computer_name, vec!["distinguishedName"])
+            .await?;
+        Ok(entry.dn)
+    }
+}
+
+#[async_trait]
+impl DnResolver for PsExecutor {
+    async fn user(&self, username: &str) -> Result<UserRecord> {
+        let cmd = PsCommand::cmdlet("Get-ADUser")
+            .param("Identity", username)
+            .param_list("Properties", USER_ATTRIBUTES)
+            .select(&["DistinguishedName", "Department", "Title", "mail", "MemberOf"]);
+        self.execute_json::<Option<UserRecord>>(cmd)
+            .await?
+            .ok_or_else(|| Error::DirectoryError(format!("Get-ADUser returned nothing for {username}")))
+    }
+
+    async fn computer_dn(&self, computer_name: &str) -> Result<String> {
+        let cmd = PsCommand::cmdlet("Get-ADComputer")
+            .param("Identity", computer_name)
+            .select(&["DistinguishedName"]);
+        self.execute_json::<Option<ComputerRecord>>(cmd)
+            .await?
+            .map(|c| c.dn)
+            .ok_or_else(|| {
+                Error::DirectoryError(format!("Get-ADComputer returned nothing for {computer_name}"))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ldap(url: &str) -> LdapConfig {
+        LdapConfig {
+            url: url.to_string(),
+            base_dn: "DC=school,DC=local".to_string(),
+            bind_dn: Some("CN=svc-logon,OU=Service,DC=school,DC=local".to_string()),
+            ..LdapConfig::default()
+        }
+    }
+
+    fn rejected(config: LdapConfig) -> String {
+        match config.validate() {
+            Err(Error::ConfigError(message)) => message,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validate_needs_url_base_dn_bind_dn_and_placeholders() {
+        assert!(ldap("ldaps://dc01.school.local").validate().is_ok());
+
+        assert!(rejected(ldap(" ")).contains("`directory.ldap.url`"));
+        assert!(
+            rejected(LdapConfig {
+                base_dn: String::new(),
+                ..ldap("ldaps://dc01")
+            })
+            .contains("`directory.ldap.base_dn`")
+        );
+        assert!(
+            rejected(LdapConfig {
+                bind_dn: None,
+                ..ldap("ldaps://dc01")
+            })
+            .contains("needs `bind_dn`")
+        );
+        // Only a simple bind needs the DN
+        for bind in [LdapBind::Anonymous, LdapBind::Gssapi] {
+            assert!(
+                LdapConfig {
+                    bind,
+                    bind_dn: None,
+                    ..ldap("ldaps://dc01")
+                }
+                .validate()
+                .is_ok()
+            );
+        }
+        assert_eq!(
+            rejected(LdapConfig {
+                computer_filter: "(cn=LIB-PC-14)".to_string(),
+                ..ldap("ldaps://dc01")
+            }),
+            "`directory.ldap.computer_filter` must contain a `{name}` placeholder"
+        );
+    }
+
+    #[test]
+    fn host_is_the_url_without_scheme_port_or_path() {
+        assert_eq!(ldap("ldaps://dc01.school.local").host(), "dc01.school.local");
+        assert_eq!(ldap("ldap://dc01.school.local:389").host(), "dc01.school.local");
+        assert_eq!(ldap("ldap://dc01.school.local/dc=school,dc=local").host(), "dc01.school.local");
+        assert_eq!(ldap("ldap://dc01:3268/").host(), "dc01");
+        assert_eq!(ldap("dc01.school.local").host(), "dc01.school.local");
+        assert_eq!(ldap("ldap://[::1]:389").host(), "::1");
+        assert_eq!(ldap("ldaps://[fd00::10]/").host(), "fd00::10");
+    }
+
+    #[test]
+    fn names_are_escaped_into_the_filter() {
+        let template = "(&(objectClass=user)(sAMAccountName={name}))";
+        assert_eq!(search_filter(template, "jsmith"), "(&(objectClass=user)(sAMAccountName=jsmith))");
+        assert_eq!(
+            search_filter(template, "*)(objectClass=*"),
+            "(&(objectClass=user)(sAMAccountName=\\2a\\29\\28objectClass=\\2a))"
+        );
+        assert_eq!(search_filter("(cn={name})", "a\\b\0c"), "(cn=a\\5cb\\00c)");
+        assert_eq!(search_filter("(cn={name}$)", "LIB-PC-14"), "(cn=LIB-PC-14$)");
+    }
+
+    // Against a local stand-in, eg: glauth with its `sample-simple.cfg`:
+    //   glauth -c sample-simple.cfg
+    //   LOGON_LOGGER_LDAP_PASSWORD=mysecret cargo test -- --ignored resolves_against_a_local_directory
+    // `LOGON_LOGGER_TEST_LDAP_*` point it at another server (url, base_dn, bind_dn, user, computer).
+    #[tokio::test]
+    #[ignore = "needs a local LDAP server"]
+    async fn resolves_against_a_local_directory() {
+        let env = |name: &str, default: &str| {
+            std::env::var(format!("LOGON_LOGGER_TEST_LDAP_{name}")).unwrap_or_else(|_| default.to_string())
+        };
+        let base_dn = env("BASE_DN", "dc=glauth,dc=com");
+        let resolver = LdapResolver::new(LdapConfig {
+            url: env("URL", "ldap://localhost:3893"),
+            base_dn: base_dn.clone(),
+            bind_dn: Some(env("BIND_DN", "cn=serviceuser,ou=svcaccts,dc=glauth,dc=com")),
+            user_filter: env("USER_FILTER", "(&(objectClass=posixAccount)(uid={name}))"),
+            computer_filter: env("COMPUTER_FILTER", "(&(objectClass=posixAccount)(uid={name}))"),
+            ..LdapConfig::default()
+        });
+        resolver.config.validate().unwrap();
+
+        let user = env("USER", "hackers");
+        let record = resolver.user(&user).await.unwrap();
+        assert!(record.dn.to_lowercase().starts_with(&format!("cn={user},")), "{}", record.dn);
+        assert!(record.dn.to_lowercase().ends_with(&base_dn.to_lowercase()), "{}", record.dn);
+
+        let computer = env("COMPUTER", "johndoe");
+        let dn = resolver.computer_dn(&computer).await.unwrap();
+        assert!(dn.to_lowercase().starts_with(&format!("cn={computer},")), "{dn}");
+
+        let missing = resolver.user("no-such-account").await.unwrap_err();
+        assert!(matches!(missing, Error::DirectoryError(_)), "{missing}");
+    }
+}
@@ -0,0 +1,466 @@
+//! RFC 4514 distinguished names, plus the rules that turn one into the `*_ou` log columns.
+
+use std::fmt::{self, Display, Write as _};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::prelude::{Error, Result};
+
+/// One `type=value` pair, `value` is stored unescaped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    pub kind:  String,
+    pub value: String,
+}
+
+/// A relative DN, multi-valued RDNs (`CN=a+UID=b`) hold more than one attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rdn {
+    pub attributes: Vec<Attribute>,
+}
+
+impl Rdn {
+    pub fn value_of(&self, kind: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.kind.eq_ignore_ascii_case(kind))
+            .map(|a| a.value.as_str())
+    }
+
+    pub fn is(&self, kind: &str) -> bool {
+        self.value_of(kind).is_some()
+    }
+}
+
+/// Parsed DN, RDNs are ordered leaf first, same as the string form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistinguishedName {
+    rdns: Vec<Rdn>,
+}
+
+impl DistinguishedName {
+    /// Value of the leaf `CN`, eg: the account's display name.
+    pub fn common_name(&self) -> Option<&str> {
+        self.rdns.first().and_then(|r| r.value_of("CN"))
+    }
+
+    /// `OU` RDNs, nearest to the object first.
+    pub fn organizational_units(&self) -> impl DoubleEndedIterator<Item = &Rdn> {
+        self.rdns.iter().filter(|r| r.is("OU"))
+    }
+}
+
+impl FromStr for DistinguishedName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(Self { rdns: vec![] });
+        }
+
+        let rdns = split_unescaped(s, ',')
+            .into_iter()
+            .map(|rdn| {
+                let attributes = split_unescaped(rdn, '+')
+                    .into_iter()
+                    .map(|ava| parse_attribute(ava, s))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Rdn { attributes })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rdns })
+    }
+}
+
+impl Display for DistinguishedName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, rdn) in self.rdns.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{rdn}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Rdn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, attr) in self.attributes.iter().enumerate() {
+            if i > 0 {
+                f.write_char('+')?;
+            }
+            write!(f, "{}={}", attr.kind, escape_value(&attr.value))?;
+        }
+        Ok(())
+    }
+}
+
+// Splits on `sep` wherever it isn't preceded by a backslash escape.
+fn split_unescaped(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == sep => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_attribute(ava: &str, dn: &str) -> Result<Attribute> {
+    let (kind, raw) = ava
+        .split_once('=')
+        .ok_or_else(|| Error::DnParseError(format!("\"{ava}\" in \"{dn}\" is missing '='")))?;
+
+    let kind = kind.trim();
+    if kind.is_empty()
+        || !kind
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        return Err(Error::DnParseError(format!("Invalid attribute type \"{kind}\" in \"{dn}\"")));
+    }
+
+    let raw = raw.trim_start();
+    // `#04...` is a BER hex string, kept verbatim
+    let value = if raw.starts_with('#') {
+        raw.trim_end().to_string()
+    } else {
+        unescape_value(raw)
+            .ok_or_else(|| Error::DnParseError(format!("Invalid escape in \"{ava}\" of \"{dn}\"")))?
+    };
+
+    Ok(Attribute {
+        kind: kind.to_string(),
+        value,
+    })
+}
+
+// Handles `\,` style and `\2C` hex-pair escapes; hex pairs may spell out multi-byte UTF-8.
+// Unescaped trailing spaces are insignificant, escaped ones (`\ `) are kept.
+fn unescape_value(raw: &str) -> Option<String> {
+    let bytes = raw.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut significant = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                let next = *bytes.get(i + 1)?;
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|h| std::str::from_utf8(h).ok())
+                    .and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 3;
+                    }
+                    None => {
+                        out.push(next);
+                        i += 2;
+                    }
+                }
+                significant = out.len();
+            }
+            b' ' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+                significant = out.len();
+            }
+        }
+    }
+
+    out.truncate(significant);
+    String::from_utf8(out).ok()
+}
+
+fn escape_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+
+    for (i, c) in value.chars().enumerate() {
+        let needs_escape = matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=')
+            || (i == 0 && matches!(c, ' ' | '#'))
+            || (i == last && c == ' ');
+        if needs_escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Which piece of an OU name to keep once it's split on `separator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "PartDef")]
+pub enum PartSelector {
+    Whole,
+    First,
+    Last,
+    Index(usize),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PartDef {
+    Index(usize),
+    Named(String),
+}
+
+impl TryFrom<PartDef> for PartSelector {
+    type Error = String;
+
+    fn try_from(def: PartDef) -> std::result::Result<Self, Self::Error> {
+        match def {
+            PartDef::Index(i) => Ok(Self::Index(i)),
+            PartDef::Named(name) => {
+                match name.to_ascii_lowercase().as_str() {
+                    "whole" => Ok(Self::Whole),
+                    "first" => Ok(Self::First),
+                    "last" => Ok(Self::Last),
+                    other => {
+                        Err(format!("expected \"whole\", \"first\", \"last\" or an index, got \"{other}\""))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How one OU column is derived from a DN.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OuRule {
+    /// 0 = the OU the object sits in, 1 = its parent, ...
+    #[serde(default)]
+    pub level:     usize,
+    /// Count `level` from the domain root instead of from the object.
+    #[serde(default)]
+    pub from_root: bool,
+    /// Split the OU name on this, eg: `"_"` for `Students_2024`.
+    #[serde(default)]
+    pub separator: Option<String>,
+    #[serde(default = "default_part")]
+    pub part:      PartSelector,
+}
+
+fn default_part() -> PartSelector {
+    PartSelector::Whole
+}
+
+impl OuRule {
+    fn ou<'a>(&self, dn: &'a DistinguishedName) -> Option<&'a Rdn> {
+        if self.from_root {
+            dn.organizational_units().rev().nth(self.level)
+        } else {
+            dn.organizational_units().nth(self.level)
+        }
+    }
+
+    /// The selected OU RDN in string form, eg: `OU=Computers_LIB`.
+    pub fn full(&self, dn: &DistinguishedName) -> Option<String> {
+        self.ou(dn).map(Rdn::to_string)
+    }
+
+    /// The selected OU's name after the separator/part rule.
+    pub fn extract(&self, dn: &DistinguishedName) -> Option<String> {
+        let name = self.ou(dn)?.value_of("OU")?;
+        let part = match (&self.separator, self.part) {
+            (None, _) | (_, PartSelector::Whole) => Some(name),
+            (Some(sep), PartSelector::First) => name.split(sep.as_str()).next(),
+            (Some(sep), PartSelector::Last) => name.rsplit(sep.as_str()).next(),
+            (Some(sep), PartSelector::Index(i)) => name.split(sep.as_str()).nth(i),
+        };
+        part.filter(|p| !p.is_empty()).map(str::to_string)
+    }
+}
+
+/// `[ou]` config table. Defaults reproduce the original script: the user's OU suffix after the
+/// first `_`, and the computer OU's suffix after the last `_`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OuConfig {
+    /// Drives `user_ou`, applied to the user DN.
+    pub user:     OuRule,
+    /// Drives `full_ou` and `ws_ou`, applied to the computer DN.
+    pub computer: OuRule,
+}
+
+impl Default for OuConfig {
+    fn default() -> Self {
+        Self {
+            user:     OuRule {
+                level:     0,
+                from_root: false,
+                separator: Some("_".to_string()),
+                part:      PartSelector::Index(1),
+            },
+            computer: OuRule {
+                level:     0,
+                from_root: false,
+                separator: Some("_".to_string()),
+                part:      PartSelector::Last,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dn(s: &str) -> DistinguishedName {
+        s.parse().unwrap()
+    }
+
+    fn rule(level: usize, from_root: bool, separator: Option<&str>, part: PartSelector) -> OuRule {
+        OuRule {
+            level,
+            from_root,
+            separator: separator.map(str::to_string),
+            part,
+        }
+    }
+
+    const COMPUTER: &str = "CN=LIB-PC-14,OU=Computers_LIB,OU=Site_North,OU=Schools,DC=school,DC=local";
+
+    #[test]
+    fn escaped_comma_stays_in_the_value() {
+        let parsed = dn(r"CN=Smith\, John,OU=Staff,DC=school,DC=local");
+        assert_eq!(parsed.common_name(), Some("Smith, John"));
+        assert_eq!(parsed.rdns.len(), 4);
+        assert_eq!(parsed.to_string(), r"CN=Smith\, John,OU=Staff,DC=school,DC=local");
+    }
+
+    #[test]
+    fn hex_escapes_decode_including_multi_byte_utf8() {
+        assert_eq!(dn(r"CN=Smith\2C John").common_name(), Some("Smith, John"));
+        assert_eq!(dn(r"CN=Ren\C3\A9e").common_name(), Some("Renée"));
+        // Not a hex pair, so a plain escape of the next character
+        assert_eq!(dn(r"CN=a\=b").common_name(), Some("a=b"));
+
+        assert!(matches!(r"CN=bad\C3".parse::<DistinguishedName>(), Err(Error::DnParseError(_))));
+        assert!(matches!(r"CN=dangling\".parse::<DistinguishedName>(), Err(Error::DnParseError(_))));
+    }
+
+    #[test]
+    fn hash_values_are_kept_verbatim() {
+        let parsed = dn("UID=#04024869 ,OU=Staff");
+        assert_eq!(parsed.rdns[0].value_of("uid"), Some("#04024869"));
+        // Escaped, it's an ordinary string starting with `#`
+        assert_eq!(dn(r"CN=\#1 Fan").common_name(), Some("#1 Fan"));
+    }
+
+    #[test]
+    fn plus_makes_a_multi_valued_rdn_unless_escaped() {
+        let parsed = dn("CN=John Smith+UID=jsmith,OU=Staff,DC=school,DC=local");
+        assert_eq!(parsed.rdns[0].attributes.len(), 2);
+        assert_eq!(parsed.rdns[0].value_of("UID"), Some("jsmith"));
+        assert_eq!(parsed.common_name(), Some("John Smith"));
+        assert_eq!(parsed.to_string(), "CN=John Smith+UID=jsmith,OU=Staff,DC=school,DC=local");
+
+        let escaped = dn(r"CN=C\+\+ Club,OU=Clubs");
+        assert_eq!(escaped.rdns[0].attributes.len(), 1);
+        assert_eq!(escaped.common_name(), Some("C++ Club"));
+    }
+
+    #[test]
+    fn only_escaped_leading_and_trailing_spaces_count() {
+        let parsed = dn(r"CN=\ padded\ ,OU=Staff");
+        assert_eq!(parsed.common_name(), Some(" padded "));
+        assert_eq!(parsed.to_string(), r"CN=\ padded\ ,OU=Staff");
+
+        assert_eq!(dn("CN=  trimmed   , OU = Staff ").common_name(), Some("trimmed"));
+        assert_eq!(dn(r"CN=one\20").common_name(), Some("one "));
+    }
+
+    #[test]
+    fn malformed_attributes_are_errors() {
+        assert!(dn("").rdns.is_empty());
+        for bad in ["CN=ok,Staff", "=value", "C N=x", "CN=a+"] {
+            assert!(matches!(bad.parse::<DistinguishedName>(), Err(Error::DnParseError(_))), "{bad} parsed");
+        }
+    }
+
+    #[test]
+    fn level_counts_from_the_object_or_the_root() {
+        let parsed = dn(COMPUTER);
+        let whole = |level, from_root| rule(level, from_root, None, PartSelector::Whole).extract(&parsed);
+
+        assert_eq!(whole(0, false).as_deref(), Some("Computers_LIB"));
+        assert_eq!(whole(1, false).as_deref(), Some("Site_North"));
+        assert_eq!(whole(0, true).as_deref(), Some("Schools"));
+        assert_eq!(whole(1, true).as_deref(), Some("Site_North"));
+        assert_eq!(whole(3, false), None);
+        assert_eq!(whole(3, true), None);
+
+        assert_eq!(
+            rule(1, false, Some("_"), PartSelector::Last)
+                .full(&parsed)
+                .as_deref(),
+            Some("OU=Site_North")
+        );
+    }
+
+    #[test]
+    fn separator_and_part_pick_a_piece_of_the_name() {
+        let parsed = dn(COMPUTER);
+        let part = |separator, part| rule(0, false, separator, part).extract(&parsed);
+
+        assert_eq!(part(Some("_"), PartSelector::First).as_deref(), Some("Computers"));
+        assert_eq!(part(Some("_"), PartSelector::Last).as_deref(), Some("LIB"));
+        assert_eq!(part(Some("_"), PartSelector::Index(1)).as_deref(), Some("LIB"));
+        assert_eq!(part(Some("_"), PartSelector::Index(2)), None);
+        assert_eq!(part(Some("_"), PartSelector::Whole).as_deref(), Some("Computers_LIB"));
+        assert_eq!(part(Some("-"), PartSelector::Last).as_deref(), Some("Computers_LIB"));
+        // No separator, nothing to split
+        assert_eq!(part(None, PartSelector::First).as_deref(), Some("Computers_LIB"));
+
+        // An empty piece is no value at all
+        let trailing = dn("CN=jsmith,OU=Staff_,DC=school");
+        assert_eq!(rule(0, false, Some("_"), PartSelector::Last).extract(&trailing), None);
+    }
+
+    #[test]
+    fn default_rules_match_the_original_script() {
+        let config = OuConfig::default();
+        let user = dn("CN=Smith\\, John,OU=Students_2024_Yr10,OU=Users,DC=school,DC=local");
+        assert_eq!(config.user.extract(&user).as_deref(), Some("2024"));
+        assert_eq!(config.computer.extract(&dn(COMPUTER)).as_deref(), Some("LIB"));
+        assert_eq!(config.computer.full(&dn(COMPUTER)).as_deref(), Some("OU=Computers_LIB"));
+    }
+
+    #[test]
+    fn rules_deserialize_from_the_config() {
+        let rule: OuRule =
+            toml::from_str("level = 1\nfrom_root = true\nseparator = \"-\"\npart = \"LAST\"").unwrap();
+        assert_eq!((rule.level, rule.from_root, rule.part), (1, true, PartSelector::Last));
+        assert_eq!(rule.separator.as_deref(), Some("-"));
+
+        let rule: OuRule = toml::from_str("part = 2").unwrap();
+        assert_eq!((rule.level, rule.from_root, rule.part), (0, false, PartSelector::Index(2)));
+        assert_eq!(toml::from_str::<OuRule>("").unwrap().part, PartSelector::Whole);
+
+        assert!(toml::from_str::<OuRule>("part = \"middle\"").is_err());
+        assert!(toml::from_str::<OuRule>("depth = 1").is_err());
+    }
+}
@@ -15,6 +15,84 @@ pub enum Error {
     #[error("Calamine XLSX error: {0}")]
     CalamaineXlsxError(#[from] calamine::XlsxError),
 
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Failed to parse config file {path}: {source}")]
+    ConfigParseError {
+        path:   std::path::PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Timetable error: {0}")]
+    TimetableError(String),
+
+    #[error("Calendar error: {0}")]
+    CalendarError(String),
+
+    #[error("Directory lookup error: {0}")]
+    DirectoryError(String),
+
+    #[error("Invalid distinguished name: {0}")]
+    DnParseError(String),
+
+    #[error("LDAP error: {0}")]
+    LdapError(#[from] ldap3::LdapError),
+
+    #[error("Failed to start command: {0}")]
+    CommandSpawnError(#[source] std::io::Error),
+
+    #[error("Command timed out after {0:?}")]
+    CommandTimeoutError(std::time::Duration),
+
+    #[error("Command exited with status {}: {stderr}", status.map_or("unknown".to_string(), |c| c.to_string()))]
+    CommandExitError { status: Option<i32>, stderr: String },
+
+    #[error("Shell host crashed: {0}")]
+    ShellCrashError(String),
+
+    #[error("Command output doesn't match {target}: {source}")]
+    SchemaMismatchError {
+        target: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("SQLite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("Unreadable journal entry at {}:{line}: {source}", path.display())]
+    JournalError {
+        path:   std::path::PathBuf,
+        line:   usize,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Timed out after {waited:?} waiting for {}, held by {owner}", path.display())]
+    LockTimeoutError {
+        path:   std::path::PathBuf,
+        owner:  String,
+        waited: std::time::Duration,
+    },
+
+    #[error("Can't write to log directory {}: {source}", path.display())]
+    ProvisionError {
+        path:   std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Archive error: {0}")]
+    ArchiveError(#[from] zip::result::ZipError),
+
+    #[error("{failed} of {checked} workbooks failed validation")]
+    ValidationError { failed: usize, checked: usize },
+
+    #[error("Log sink error: {0}")]
+    SinkError(String),
+
     #[error("Environment variable error: {0}")]
     EnvironVarError(#[from] std::env::VarError),
 
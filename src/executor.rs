@@ -1,48 +1,345 @@
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use eyre::Context;
-use tokio::sync::{mpsc, oneshot};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::{Mutex, mpsc, oneshot};
 
+use crate::prelude::{Error, Result};
+use crate::shell::{ShellBackend, ShellKind, ShellSession};
+
+/// A command built from trusted cmdlet/parameter names and untrusted values.
+/// The `ShellBackend` renders it, values only ever reach the script through the backend's `quote`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsCommand {
+    pub(crate) name:   &'static str,
+    /// Each parameter's values, more than one renders as a list (`'a','b'`).
+    pub(crate) params: Vec<(&'static str, Vec<String>)>,
+    /// `Select-Object -Property`, empty keeps the whole object.
+    pub(crate) select: Vec<&'static str>,
+    /// Output piped through `ConvertTo-Json -Compress`, set by `PsExecutor::execute_json`.
+    pub(crate) json:   bool,
+}
+
+impl PsCommand {
+    /// Starts the command with `name`, eg: `Get-ADUser`.
+    pub fn cmdlet(name: &'static str) -> Self {
+        Self {
+            name,
+            params: vec![],
+            select: vec![],
+            json: false,
+        }
+    }
+
+    /// `-Name '<value>'`, the value is quoted as a literal.
+    pub fn param(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.params.push((name, vec![value.into()]));
+        self
+    }
+
+    /// `-Name 'a','b'`, each value quoted as a literal.
+    pub fn param_list<S: Into<String>>(
+        mut self,
+        name: &'static str,
+        values: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.params
+            .push((name, values.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Only keep these properties of the returned object(s).
+    pub fn select(mut self, properties: &[&'static str]) -> Self {
+        self.select.extend_from_slice(properties);
+        self
+    }
+}
+
+/// `[executor]` config table, applies to every PowerShell command.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutorConfig {
+    pub shell:              ShellKind,
+    /// Canned responses for `shell = "mock"`, relative paths resolve against the config file.
+    pub mock:               Option<PathBuf>,
+    /// Keep long-lived shell hosts around instead of one process per command (PowerShell only).
+    pub persistent:         bool,
+    /// Number of workers (and hosts) running commands concurrently.
+    pub pool_size:          usize,
+    /// Per attempt, the child is killed once it runs over.
+    pub timeout_secs:       u64,
+    /// Extra attempts after the first one times out or its PowerShell host crashes.
+    pub retries:            u32,
+    /// Also retry commands that exited non-zero. Off by default: "not found" and friends come back
+    /// the same every time, retrying only delays the logon.
+    pub retry_exit_errors:  bool,
+    /// Wait before the first retry, multiplied by `backoff_multiplier` for each one after.
+    pub backoff_ms:         u64,
+    pub backoff_multiplier: u32,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            shell:              ShellKind::default(),
+            mock:               None,
+            persistent:         true,
+            // The user and computer lookups run side by side
+            pool_size:          2,
+            timeout_secs:       15,
+            retries:            2,
+            retry_exit_errors:  false,
+            backoff_ms:         500,
+            backoff_multiplier: 2,
+        }
+    }
+}
+
+impl ExecutorConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    // Timeouts and crashed hosts may go away on another attempt. A missing powershell.exe won't,
+    // and a non-zero exit is the command's answer unless `retry_exit_errors` says otherwise.
+    fn is_transient(&self, error: &Error) -> bool {
+        match error {
+            Error::CommandTimeoutError(_) | Error::ShellCrashError(_) => true,
+            Error::CommandExitError { .. } => self.retry_exit_errors,
+            _ => false,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.backoff_multiplier.max(1).saturating_pow(attempt);
+        Duration::from_millis(self.backoff_ms.saturating_mul(u64::from(factor)))
+    }
+}
+
+type Request = (PsCommand, oneshot::Sender<Result<String>>);
+
+/// Runs commands through a shell backend on `pool_size` workers, each keeping its own session (when
+/// `persistent` and the backend supports one).
 pub struct PsExecutor {
-    pub tx: mpsc::Sender<(String, oneshot::Sender<std::result::Result<String, eyre::Report>>)>,
+    pub tx: mpsc::Sender<Request>,
 }
 
 impl PsExecutor {
-    pub fn new() -> Arc<Self> {
-        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    pub fn new(backend: Arc<dyn ShellBackend>, config: ExecutorConfig) -> Arc<Self> {
+        let (tx, rx) = tokio::sync::mpsc::channel::<Request>(8);
         let executor = Arc::new(PsExecutor { tx });
-        let worker = executor.clone();
-
-        tokio::spawn(async move {
-            while let Some((command, responder)) = rx.recv().await {
-                let result = tokio::process::Command::new("powershell.exe")
-                    .args(["-NoProfile", "-Command", &command])
-                    .output()
-                    .await
-                    .context("Powershell spawn failed")
-                    .and_then(|out| {
-                        if out.status.success() {
-                            Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
-                        } else {
-                            Err(eyre::eyre!(
-                                "Powershell command failed: {}",
-                                String::from_utf8_lossy(&out.stderr)
-                            ))
-                        }
-                    });
-                let _ = responder.send(result);
-            }
-        });
+        let rx = Arc::new(Mutex::new(rx));
 
-        worker
+        for _ in 0..config.pool_size.max(1) {
+            tokio::spawn(worker(backend.clone(), rx.clone(), config.clone()));
+        }
+
+        executor
     }
 
-    pub async fn execute(&self, command: String) -> std::result::Result<String, eyre::Report> {
-        let (responder, receiver) = oneshot::channel::<std::result::Result<String, eyre::Report>>();
+    pub async fn execute(&self, command: PsCommand) -> Result<String> {
+        let (responder, receiver) = oneshot::channel::<Result<String>>();
         self.tx
             .send((command, responder))
             .await
-            .context("Failed to send command to executor")?;
-        receiver.await?
+            .map_err(|_| Error::Generic("PowerShell executor has shut down".to_string()))?;
+        receiver
+            .await
+            .map_err(|_| Error::Generic("PowerShell executor dropped the command".to_string()))?
+    }
+
+    /// Runs `command` with its output converted to JSON and deserialized into `T`.
+    /// No output at all is treated as JSON `null`, so `Option<T>` covers "nothing found".
+    pub async fn execute_json<T: DeserializeOwned>(&self, mut command: PsCommand) -> Result<T> {
+        command.json = true;
+        let out = self.execute(command).await?;
+        let json = if out.is_empty() { "null" } else { out.as_str() };
+        serde_json::from_str(json).map_err(|source| {
+            Error::SchemaMismatchError {
+                target: std::any::type_name::<T>(),
+                source,
+            }
+        })
+    }
+}
+
+async fn worker(
+    backend: Arc<dyn ShellBackend>,
+    rx: Arc<Mutex<mpsc::Receiver<Request>>>,
+    config: ExecutorConfig,
+) {
+    // Started lazily by the first command, dropping it kills the host
+    let mut session: Option<Box<dyn ShellSession>> = None;
+
+    loop {
+        let Some((command, mut responder)) = rx.lock().await.recv().await else {
+            break;
+        };
+        // Caller gave up (eg: its own timeout) while queued, don't start the process at all
+        if responder.is_closed() {
+            continue;
+        }
+
+        let script = backend.render(&command);
+        let cancelled = tokio::select! {
+            result = run_with_retry(backend.as_ref(), &mut session, &script, &config) => {
+                let _ = responder.send(result);
+                false
+            }
+            // Dropping `run_with_retry` drops any one-shot child, `kill_on_drop` takes care of the rest
+            _ = responder.closed() => true,
+        };
+        // A session abandoned mid-command still owes a response, start a clean one next time
+        if cancelled {
+            session = None;
+        }
+    }
+}
+
+async fn run_with_retry(
+    backend: &dyn ShellBackend,
+    session: &mut Option<Box<dyn ShellSession>>,
+    script: &str,
+    config: &ExecutorConfig,
+) -> Result<String> {
+    let mut attempt = 0;
+    loop {
+        match run_attempt(backend, session, script, config).await {
+            Err(e) if attempt < config.retries && config.is_transient(&e) => {
+                tracing::warn!("Shell attempt {} failed, retrying: {}", attempt + 1, e);
+                tokio::time::sleep(config.backoff(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn run_attempt(
+    backend: &dyn ShellBackend,
+    session: &mut Option<Box<dyn ShellSession>>,
+    script: &str,
+    config: &ExecutorConfig,
+) -> Result<String> {
+    if config.persistent && session.is_none() {
+        *session = backend.open_session().await?;
+    }
+    let Some(active) = session.as_mut() else {
+        return backend.run(script, config.timeout()).await;
+    };
+
+    let result = active.run(script, config.timeout()).await;
+    // Crashed or timed out host, the next attempt starts a fresh one
+    if !active.healthy() {
+        *session = None;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex as StdMutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+
+    /// Answers each run with the next scripted result, counting the runs.
+    struct Scripted {
+        results: StdMutex<VecDeque<Result<String>>>,
+        runs:    Arc<StdMutex<usize>>,
+    }
+
+    #[async_trait]
+    impl ShellBackend for Scripted {
+        fn quote(&self, value: &str) -> String {
+            format!("'{value}'")
+        }
+
+        async fn run(&self, _script: &str, _timeout: Duration) -> Result<String> {
+            *self.runs.lock().unwrap() += 1;
+            self.results
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| Ok("ok".to_string()))
+        }
+    }
+
+    fn not_found() -> Error {
+        Error::CommandExitError {
+            status: Some(1),
+            stderr: "Cannot find an object with identity: 'ghost'".to_string(),
+        }
+    }
+
+    // Runs one command against `results`, returning its outcome and how many attempts it took.
+    async fn run(results: Vec<Error>, config: ExecutorConfig) -> (Result<String>, usize) {
+        let runs = Arc::new(StdMutex::new(0));
+        let backend = Scripted {
+            results: StdMutex::new(results.into_iter().map(Err).collect()),
+            runs:    runs.clone(),
+        };
+        let config = ExecutorConfig {
+            persistent: false,
+            pool_size: 1,
+            backoff_ms: 1,
+            ..config
+        };
+        let result = PsExecutor::new(Arc::new(backend), config)
+            .execute(PsCommand::cmdlet("Get-ADUser").param("Identity", "ghost"))
+            .await;
+        let runs = *runs.lock().unwrap();
+        (result, runs)
+    }
+
+    #[tokio::test]
+    async fn non_zero_exit_fails_without_retrying() {
+        let (result, runs) = run(vec![not_found()], ExecutorConfig::default()).await;
+        assert!(matches!(result, Err(Error::CommandExitError { status: Some(1), .. })));
+        assert_eq!(runs, 1);
+    }
+
+    #[tokio::test]
+    async fn timeouts_and_crashed_hosts_are_retried() {
+        let failures = vec![
+            Error::CommandTimeoutError(Duration::from_secs(15)),
+            Error::ShellCrashError("PowerShell host exited with status -1".to_string()),
+        ];
+        let (result, runs) = run(failures, ExecutorConfig::default()).await;
+        assert_eq!(result.unwrap(), "ok");
+        assert_eq!(runs, 3);
+
+        // Out of retries, the last failure is the answer
+        let failures = vec![
+            Error::CommandTimeoutError(Duration::from_secs(15)),
+            Error::CommandTimeoutError(Duration::from_secs(15)),
+            Error::CommandTimeoutError(Duration::from_secs(15)),
+        ];
+        let (result, runs) = run(failures, ExecutorConfig::default()).await;
+        assert!(matches!(result, Err(Error::CommandTimeoutError(_))));
+        assert_eq!(runs, 3);
+    }
+
+    #[tokio::test]
+    async fn exits_are_retried_when_configured() {
+        let config = ExecutorConfig {
+            retry_exit_errors: true,
+            ..ExecutorConfig::default()
+        };
+        let (result, runs) = run(vec![not_found()], config).await;
+        assert_eq!(result.unwrap(), "ok");
+        assert_eq!(runs, 2);
+    }
+
+    #[tokio::test]
+    async fn spawn_failures_are_never_retried() {
+        let missing = Error::CommandSpawnError(std::io::ErrorKind::NotFound.into());
+        let (result, runs) = run(vec![missing], ExecutorConfig::default()).await;
+        assert!(matches!(result, Err(Error::CommandSpawnError(_))));
+        assert_eq!(runs, 1);
     }
 }
@@ -0,0 +1,365 @@
+//! Reading logged entries back out of the `xlsx` sinks' workbooks (and whatever their journals still
+//! hold), for the maintenance subcommands: `query`, `report`, `merge`, `validate`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use calamine::{Data, DataType};
+
+use crate::config::Config;
+use crate::lock::FileLock;
+use crate::prelude::{Error, Result};
+use crate::sink::{LogFamily, SinkConfig};
+use crate::user_entry::UserEntry;
+use crate::workstation::WorkStationEntry;
+use crate::{ExcelLoggable, append, journal};
+
+/// What one workbook holds, journal included.
+#[derive(Default)]
+pub struct Contents {
+    pub entries:   Vec<WorkStationEntry>,
+    /// Sheet row numbers (1 is the header) of rows that couldn't be parsed.
+    pub bad_rows:  Vec<u32>,
+    /// Set when the header isn't (a prefix of) the family's columns.
+    pub header:    Option<String>,
+    /// The workbook was missing or corrupt, what it holds came from its `.bak`.
+    pub recovered: bool,
+}
+
+/// Workbooks of every `xlsx` sink of `family` (both when `None`), including ones that only have a
+/// journal so far, or only their `.bak` left.
+pub fn workbooks(config: &Config, family: Option<LogFamily>) -> Result<Vec<(LogFamily, PathBuf)>> {
+    let mut found = BTreeSet::new();
+    for sink in SinkConfig::configured(config) {
+        if !matches!(sink, SinkConfig::Xlsx { .. }) {
+            continue;
+        }
+        let Some(files) = sink.files(config)? else {
+            continue;
+        };
+        if family.is_some_and(|family| family != files.family()) {
+            continue;
+        }
+
+        for path in files.walk()? {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let workbook = [".journal", ".compacting", ".bak"]
+                .iter()
+                .find_map(|suffix| name.strip_suffix(suffix))
+                .unwrap_or(name);
+            if files.is_ours(workbook) {
+                found.insert((files.family(), path.with_file_name(workbook)));
+            }
+        }
+    }
+    Ok(found.into_iter().collect())
+}
+
+/// Reads the workbook at `path` (or its `.bak`, as the `xlsx` sink would) and its journal.
+pub async fn read(path: &Path, family: LogFamily, worksheet_name: &str) -> Result<Contents> {
+    let mut contents = {
+        let path = path.to_path_buf();
+        let worksheet_name = worksheet_name.to_string();
+        tokio::task::spawn_blocking(move || read_sheet(&path, family, &worksheet_name)).await??
+    };
+
+    for journal in [journal::journal_path(path), journal::compacting_path(path)] {
+        if journal.exists() {
+            contents.entries.extend(journal::read(&journal).await?);
+        }
+    }
+    Ok(contents)
+}
+
+fn read_sheet(path: &Path, family: LogFamily, worksheet_name: &str) -> Result<Contents> {
+    match family {
+        LogFamily::Workstation => read_rows::<WorkStationEntry>(path, worksheet_name),
+        LogFamily::User => read_rows::<UserEntry>(path, worksheet_name),
+    }
+}
+
+// Through `append::read_existing`, so what's read back is what the next append would keep. The header
+// is checked against `E`'s columns and every row goes through `E::parse_row`.
+fn read_rows<E: ExcelLoggable + Into<WorkStationEntry>>(
+    path: &Path,
+    worksheet_name: &str,
+) -> Result<Contents> {
+    let (sheet, recovered) = append::read_existing::<E>(path, worksheet_name)?;
+    Ok(Contents {
+        entries: sheet.entries.into_iter().map(Into::into).collect(),
+        bad_rows: sheet.bad_rows,
+        header: sheet.header.and_then(|header| check_header(&header, E::COLUMNS)),
+        recovered,
+    })
+}
+
+// Older workbooks stop after `Serial_Number`, anything they do have must match.
+fn check_header(header: &[Data], columns: &[&str]) -> Option<String> {
+    const REQUIRED: usize = 13;
+
+    let cells: Vec<String> = header
+        .iter()
+        .map(|cell| cell.as_string().unwrap_or_default())
+        .take_while(|cell| !cell.is_empty())
+        .collect();
+    if let Some((i, (found, expected))) = cells
+        .iter()
+        .zip(columns)
+        .enumerate()
+        .find(|(_, (found, expected))| found != *expected)
+    {
+        return Some(format!("column {} is \"{found}\", expected \"{expected}\"", i + 1));
+    }
+    if cells.len() < REQUIRED {
+        return Some(format!(
+            "only {} columns, expected at least {REQUIRED} ({})",
+            cells.len(),
+            columns[..REQUIRED].join(", ")
+        ));
+    }
+    None
+}
+
+/// Every entry of `family`, oldest first, across all its workbooks. Unreadable workbooks are skipped
+/// with a warning.
+pub async fn load(config: &Config, family: LogFamily) -> Result<Vec<WorkStationEntry>> {
+    let mut entries = vec![];
+    for (_, path) in workbooks(config, Some(family))? {
+        match read(&path, family, &config.worksheet_name).await {
+            Ok(contents) => entries.extend(contents.entries),
+            Err(e) => tracing::warn!("Skipping unreadable {}: {}", path.display(), e),
+        }
+    }
+    entries.sort_by_key(|entry| entry.date_time);
+    Ok(entries)
+}
+
+/// Checks every workbook (and journal) can be read back: the header matches the family, every row
+/// parses. `paths` limits it to those workbooks, read as `family`.
+pub async fn validate(config: &Config, family: Option<LogFamily>, paths: Vec<PathBuf>) -> Result<()> {
+    let workbooks = if paths.is_empty() {
+        workbooks(config, family)?
+    } else {
+        let family = family.unwrap_or(LogFamily::Workstation);
+        paths.into_iter().map(|path| (family, path)).collect()
+    };
+
+    let mut failed = 0;
+    for (family, path) in &workbooks {
+        let exists = [
+            path.clone(),
+            append::backup_path(path),
+            journal::journal_path(path),
+            journal::compacting_path(path),
+        ]
+        .iter()
+        .any(|file| file.exists());
+        let problems = match read(path, *family, &config.worksheet_name).await {
+            Ok(_) if !exists => vec!["no such workbook".to_string()],
+            Ok(contents) => {
+                let mut problems = vec![];
+                if contents.recovered {
+                    problems.push(format!(
+                        "missing or corrupt, read from {}",
+                        append::backup_path(path).display()
+                    ));
+                }
+                if let Some(header) = contents.header {
+                    problems.push(format!("header: {header}"));
+                }
+                if !contents.bad_rows.is_empty() {
+                    let rows: Vec<String> = contents.bad_rows.iter().map(u32::to_string).collect();
+                    problems.push(format!("unreadable rows: {}", rows.join(", ")));
+                }
+                if problems.is_empty() {
+                    println!("ok    {} ({} entries)", path.display(), contents.entries.len());
+                }
+                problems
+            }
+            Err(e) => vec![e.to_string()],
+        };
+
+        if !problems.is_empty() {
+            failed += 1;
+            println!("FAIL  {}", path.display());
+            for problem in problems {
+                println!("      {problem}");
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::ValidationError {
+            failed,
+            checked: workbooks.len(),
+        });
+    }
+    println!("{} workbooks ok", workbooks.len());
+    Ok(())
+}
+
+/// Merges the entries of `inputs` into the `family` workbook at `output` (created if need be),
+/// skipping entries it already has. Identical rows (the same logon twice in one second) are kept as
+/// many times as the input holding the most of them has them, so overlapping copies merge cleanly.
+pub async fn merge(config: &Config, family: LogFamily, inputs: Vec<PathBuf>, output: &Path) -> Result<()> {
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let _lock = FileLock::acquire(output, &config.lock).await?;
+
+    let mut have: BTreeMap<Vec<String>, usize> = BTreeMap::new();
+    for entry in read(output, family, &config.worksheet_name).await?.entries {
+        *have.entry(entry.field_values()).or_default() += 1;
+    }
+
+    let mut merged = vec![];
+    let mut duplicates = 0;
+    for input in &inputs {
+        if !input.exists() {
+            return Err(Error::Generic(format!("{} does not exist", input.display())));
+        }
+        let mut in_input: BTreeMap<Vec<String>, usize> = BTreeMap::new();
+        for entry in read(input, family, &config.worksheet_name).await?.entries {
+            let key = entry.field_values();
+            let count = in_input.entry(key.clone()).or_default();
+            *count += 1;
+            let had = have.entry(key).or_default();
+            if *count > *had {
+                *had = *count;
+                merged.push(entry);
+            } else {
+                duplicates += 1;
+            }
+        }
+    }
+
+    let added = merged.len();
+    if added > 0 {
+        match family {
+            LogFamily::Workstation => append::append_log(&config.worksheet_name, output, merged).await?,
+            LogFamily::User => {
+                let merged = merged.into_iter().map(UserEntry::from).collect();
+                append::append_log(&config.worksheet_name, output, merged).await?
+            }
+        }
+    }
+    println!(
+        "Merged {added} entries from {} workbooks into {} ({duplicates} duplicates skipped)",
+        inputs.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn user_workbooks_are_read_with_the_user_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user_log.xlsx");
+        let logged_on = Local.with_ymd_and_hms(2026, 3, 2, 8, 40, 0).unwrap();
+        let entries: Vec<UserEntry> = ["jsmith", "adoe"]
+            .iter()
+            .map(|user| UserEntry::from(WorkStationEntry::sample(user, logged_on)))
+            .collect();
+        append::append_log("Logons", &path, entries).await.unwrap();
+
+        let contents = read(&path, LogFamily::User, "Logons").await.unwrap();
+        assert_eq!(contents.header, None);
+        assert!(contents.bad_rows.is_empty());
+        assert_eq!(
+            contents
+                .entries
+                .iter()
+                .map(|e| e.username.as_str())
+                .collect::<Vec<_>>(),
+            ["jsmith", "adoe"]
+        );
+        assert_eq!(contents.entries[0].date_time, logged_on);
+        assert_eq!(contents.entries[0].computer_name, "LIB-PC-14");
+
+        // Read as the other family, the header gives it away
+        let contents = read(&path, LogFamily::Workstation, "Logons").await.unwrap();
+        assert_eq!(contents.header.as_deref(), Some("column 1 is \"ComputerName\", expected \"Username\""));
+    }
+
+    fn config(dir: &Path) -> Config {
+        let toml =
+            format!("[[sinks]]\ntype = \"xlsx\"\nfamily = \"workstation\"\npath = '{}'\n", dir.display());
+        Config::for_test(dir, &toml).unwrap()
+    }
+
+    // The sink's workbook for 2026-03-02, saved twice so its `.bak` holds the first save.
+    async fn two_saves(config: &Config) -> PathBuf {
+        let logged_on = Local.with_ymd_and_hms(2026, 3, 2, 8, 40, 0).unwrap();
+        let path = SinkConfig::configured(config)[0]
+            .files(config)
+            .unwrap()
+            .unwrap()
+            .path(&logged_on);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        for user in ["first", "second"] {
+            append::append_log("Logons", &path, vec![WorkStationEntry::sample(user, logged_on)])
+                .await
+                .unwrap();
+        }
+        path
+    }
+
+    fn usernames(contents: &Contents) -> Vec<&str> {
+        let mut users: Vec<_> = contents.entries.iter().map(|e| e.username.as_str()).collect();
+        users.sort();
+        users
+    }
+
+    #[tokio::test]
+    async fn only_the_backup_left_is_read_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        let path = two_saves(&config).await;
+        // A save cut off between its two renames
+        std::fs::remove_file(&path).unwrap();
+
+        let contents = read(&path, LogFamily::Workstation, "Logons").await.unwrap();
+        assert!(contents.recovered);
+        assert_eq!(usernames(&contents), ["first"]);
+
+        // Still found, and flagged
+        assert_eq!(workbooks(&config, None).unwrap(), [(LogFamily::Workstation, path.clone())]);
+        assert!(matches!(
+            validate(&config, None, vec![]).await,
+            Err(Error::ValidationError {
+                failed:  1,
+                checked: 1,
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn corrupt_workbook_is_read_from_its_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        let path = two_saves(&config).await;
+        std::fs::write(&path, b"PK\x03\x04 not really").unwrap();
+
+        let contents = read(&path, LogFamily::Workstation, "Logons").await.unwrap();
+        assert!(contents.recovered);
+        assert_eq!(usernames(&contents), ["first"]);
+    }
+
+    #[tokio::test]
+    async fn workbook_without_our_worksheet_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        let path = two_saves(&config).await;
+
+        let contents = read(&path, LogFamily::Workstation, "Other").await.unwrap();
+        assert!(contents.entries.is_empty() && contents.header.is_none() && !contents.recovered);
+    }
+}
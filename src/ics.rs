@@ -0,0 +1,245 @@
+//! Minimal iCalendar (RFC 5545) reader, only pulls the all-day/ranged VEVENTs we need for the term calendar.
+
+use chrono::{Days, NaiveDate};
+use serde::Deserialize;
+
+use crate::calendar::DateRange;
+use crate::prelude::{Error, Result};
+
+/// Which events count as what, matched case-insensitively against `SUMMARY` and `CATEGORIES`.
+/// An event matching several lists is classified by the first hit in staff day > holiday > term order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IcsMapping {
+    pub terms:      Vec<String>,
+    pub holidays:   Vec<String>,
+    pub staff_days: Vec<String>,
+}
+
+impl Default for IcsMapping {
+    fn default() -> Self {
+        Self {
+            terms:      vec!["Term".to_string()],
+            holidays:   vec!["Public Holiday".to_string(), "Holiday".to_string()],
+            staff_days: vec![
+                "Staff Day".to_string(),
+                "Pupil Free".to_string(),
+                "Staff Development".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportedCalendar {
+    pub terms:      Vec<DateRange>,
+    pub holidays:   Vec<DateRange>,
+    pub staff_days: Vec<DateRange>,
+}
+
+#[derive(Debug, Default)]
+struct RawEvent {
+    summary:    Option<String>,
+    categories: Option<String>,
+    start:      Option<NaiveDate>,
+    end:        Option<NaiveDate>,
+}
+
+pub fn import_ics(raw: &str, mapping: &IcsMapping) -> Result<ImportedCalendar> {
+    let mut imported = ImportedCalendar::default();
+    let mut current: Option<RawEvent> = None;
+
+    for (line_no, line) in unfold(raw) {
+        let Some((name, value)) = split_property(&line) else {
+            continue;
+        };
+
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => current = Some(RawEvent::default()),
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                let event = current.take().unwrap_or_default();
+                classify(event, mapping, &mut imported);
+            }
+            ("SUMMARY", Some(event)) => event.summary = Some(unescape(value)),
+            ("CATEGORIES", Some(event)) => event.categories = Some(unescape(value)),
+            ("DTSTART", Some(event)) => event.start = Some(parse_date(value, line_no)?),
+            ("DTEND", Some(event)) => {
+                let end = parse_date(value, line_no)?;
+                // All-day (`VALUE=DATE`) DTEND is exclusive, timed events end on the day they end
+                event.end = Some(if !value.contains('T') {
+                    end.checked_sub_days(Days::new(1)).unwrap_or(end)
+                } else {
+                    end
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(imported)
+}
+
+fn classify(event: RawEvent, mapping: &IcsMapping, out: &mut ImportedCalendar) {
+    let Some(start) = event.start else {
+        return;
+    };
+    let summary = event.summary.unwrap_or_default();
+    let haystack = format!("{} {}", summary, event.categories.unwrap_or_default()).to_lowercase();
+    let matches = |needles: &[String]| {
+        needles
+            .iter()
+            .any(|n| !n.is_empty() && haystack.contains(&n.to_lowercase()))
+    };
+
+    // DTEND of an all-day event on the same day collapses to the day before, clamp back to start
+    let end = event.end.unwrap_or(start).max(start);
+    let range = DateRange::new(summary, start, end);
+
+    if matches(&mapping.staff_days) {
+        out.staff_days.push(range);
+    } else if matches(&mapping.holidays) {
+        out.holidays.push(range);
+    } else if matches(&mapping.terms) {
+        out.terms.push(range);
+    }
+}
+
+// RFC 5545 3.1: lines starting with a space or tab continue the previous one. Each logical line
+// keeps the number of the physical line it starts on, so errors point at the file as written.
+fn unfold(raw: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (idx, line) in raw.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, prev))) => prev.push_str(rest),
+            _ => lines.push((idx + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+/// `NAME;PARAM=x:value` -> (`NAME`, `value`), parameters are dropped, the value shape is enough for us.
+fn split_property(line: &str) -> Option<(String, &str)> {
+    let (head, value) = line.split_once(':')?;
+    let name = head.split_once(';').map_or(head, |(name, _)| name);
+    Some((name.trim().to_ascii_uppercase(), value.trim()))
+}
+
+// `20260128`, `20260128T090000` and `20260128T090000Z` all reduce to the date.
+fn parse_date(value: &str, line_no: usize) -> Result<NaiveDate> {
+    let date = value.get(..8).unwrap_or(value);
+    NaiveDate::parse_from_str(date, "%Y%m%d")
+        .map_err(|e| Error::CalendarError(format!("Line {line_no}: invalid date \"{value}\" ({e})")))
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push(' '),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn ics(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{events}END:VCALENDAR\r\n")
+    }
+
+    fn event(summary: &str, start: &str, end: &str) -> String {
+        format!("BEGIN:VEVENT\r\nSUMMARY:{summary}\r\nDTSTART{start}\r\nDTEND{end}\r\nEND:VEVENT\r\n")
+    }
+
+    fn span(range: &DateRange) -> (&str, NaiveDate, NaiveDate) {
+        (range.name.as_str(), range.start, range.end())
+    }
+
+    #[test]
+    fn all_day_dtend_is_exclusive() {
+        let raw = ics(&[
+            event("Term 2", ";VALUE=DATE:20260420", ";VALUE=DATE:20260627"),
+            event("Anzac Day Public Holiday", ";VALUE=DATE:20260425", ";VALUE=DATE:20260426"),
+            // Some exporters write single days with DTEND = DTSTART
+            event("King's Birthday Holiday", ";VALUE=DATE:20260608", ";VALUE=DATE:20260608"),
+            // Timed events end on the day they end
+            event("Pupil Free Day", ":20260629T083000", ":20260630T150000Z"),
+        ]
+        .concat());
+
+        let imported = import_ics(&raw, &IcsMapping::default()).unwrap();
+        assert_eq!(span(&imported.terms[0]), ("Term 2", date("2026-04-20"), date("2026-06-26")));
+        assert_eq!(
+            imported.holidays.iter().map(span).collect::<Vec<_>>(),
+            [
+                ("Anzac Day Public Holiday", date("2026-04-25"), date("2026-04-25")),
+                ("King's Birthday Holiday", date("2026-06-08"), date("2026-06-08")),
+            ]
+        );
+        assert_eq!(span(&imported.staff_days[0]), ("Pupil Free Day", date("2026-06-29"), date("2026-06-30")));
+    }
+
+    #[test]
+    fn staff_days_win_over_holidays_which_win_over_terms() {
+        let raw = ics(&[
+            event("Term 1 Staff Day", ";VALUE=DATE:20260128", ";VALUE=DATE:20260129"),
+            event("Term 1 Holiday", ";VALUE=DATE:20260126", ";VALUE=DATE:20260127"),
+            event("Term 1", ";VALUE=DATE:20260128", ";VALUE=DATE:20260404"),
+            event("Athletics Carnival", ";VALUE=DATE:20260312", ";VALUE=DATE:20260313"),
+            "BEGIN:VEVENT\r\nSUMMARY:Closure\r\nCATEGORIES:PUPIL FREE\r\nDTSTART;VALUE=DATE:20260501\r\nEND:VEVENT\r\n"
+                .to_string(),
+        ]
+        .concat());
+
+        let imported = import_ics(&raw, &IcsMapping::default()).unwrap();
+        let names = |ranges: &[DateRange]| ranges.iter().map(|r| r.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&imported.staff_days), ["Term 1 Staff Day", "Closure"]);
+        assert_eq!(names(&imported.holidays), ["Term 1 Holiday"]);
+        assert_eq!(names(&imported.terms), ["Term 1"]);
+        // No DTEND is a single day
+        assert_eq!(imported.staff_days[1].end(), date("2026-05-01"));
+    }
+
+    #[test]
+    fn folded_lines_are_joined_and_values_unescaped() {
+        // Only the one leading space or tab of a continuation is dropped
+        let raw = "BEGIN:VEVENT\n\
+                   SUMMARY:Term 3\\, \n\tSemester 2\\nOpening\n\
+                   DTSTART;VALUE=DATE:2026\n 0720\n\
+                   END:VEVENT\n";
+
+        let imported = import_ics(raw, &IcsMapping::default()).unwrap();
+        assert_eq!(
+            span(&imported.terms[0]),
+            ("Term 3, Semester 2 Opening", date("2026-07-20"), date("2026-07-20"))
+        );
+    }
+
+    #[test]
+    fn errors_give_the_physical_line_number() {
+        let raw = ics(
+            "BEGIN:VEVENT\r\nSUMMARY:A very long summary that the exporter\r\n  folded over\r\n  three lines\r\n\
+             DTSTART;VALUE=DATE:20261301\r\nEND:VEVENT\r\n",
+        );
+        let message = import_ics(&raw, &IcsMapping::default()).unwrap_err().to_string();
+        assert!(message.contains("Line 7: invalid date \"20261301\""), "{message}");
+
+        // A folded bad value points at the line it starts on
+        let raw = ics("BEGIN:VEVENT\r\nDTSTART;VALUE=DATE:\r\n 2026-01-28\r\nEND:VEVENT\r\n");
+        let message = import_ics(&raw, &IcsMapping::default()).unwrap_err().to_string();
+        assert!(message.contains("Line 4:"), "{message}");
+    }
+}
@@ -0,0 +1,133 @@
+//! Append-only journal in front of each workbook. A logon costs one line appended to
+//! `<workbook>.xlsx.journal`, the workbook is only rewritten when the journal is compacted.
+
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::prelude::{Error, Result};
+use crate::workstation::WorkStationEntry;
+
+const JOURNAL_EXT: &str = "journal";
+// A journal mid-compaction, renamed so new logons start a fresh journal meanwhile.
+const COMPACTING_EXT: &str = "compacting";
+// Next to the journal: how many entries it held at what size, so appends needn't read it back.
+const COUNT_EXT: &str = "count";
+
+fn sibling(workbook: &Path, ext: &str) -> PathBuf {
+    let mut path = workbook.as_os_str().to_owned();
+    path.push(format!(".{ext}"));
+    PathBuf::from(path)
+}
+
+pub fn journal_path(workbook: &Path) -> PathBuf {
+    sibling(workbook, JOURNAL_EXT)
+}
+
+pub fn compacting_path(workbook: &Path) -> PathBuf {
+    sibling(workbook, COMPACTING_EXT)
+}
+
+/// Appends `entries` as JSON lines, returning how many entries the journal now holds. The caller
+/// holds the workbook's lock.
+pub async fn append(path: &Path, entries: &[WorkStationEntry]) -> Result<usize> {
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&serde_json::to_string(entry).map_err(|e| Error::Generic(e.to_string()))?);
+        out.push('\n');
+    }
+
+    let size = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    // The tally only counts if the journal is still the size it recorded, otherwise (a writer that
+    // died between the two, an older version) the journal is counted the slow way, once
+    let held = match read_count(path).await {
+        Some((held, counted_size)) if counted_size == size => held,
+        _ => count(path).await?,
+    };
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    // One write, so concurrent writers interleave whole batches rather than partial lines
+    file.write_all(out.as_bytes()).await?;
+    file.flush().await?;
+    drop(file);
+
+    let held = held + entries.len();
+    let tally = format!("{held} {}\n", size + out.len() as u64);
+    if let Err(e) = tokio::fs::write(sibling(path, COUNT_EXT), tally).await {
+        tracing::warn!("Couldn't update the entry count of {}: {}", path.display(), e);
+    }
+    Ok(held)
+}
+
+/// Forgets the journal's tally, for when the journal is moved away to be compacted.
+pub async fn reset_count(path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(sibling(path, COUNT_EXT)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// `(entries, journal size)` as the last append left them.
+async fn read_count(path: &Path) -> Option<(usize, u64)> {
+    let tally = tokio::fs::read_to_string(sibling(path, COUNT_EXT)).await.ok()?;
+    let (held, size) = tally.trim().split_once(' ')?;
+    Some((held.parse().ok()?, size.parse().ok()?))
+}
+
+/// Number of entries in the journal, 0 when there isn't one.
+async fn count(path: &Path) -> Result<usize> {
+    match tokio::fs::read(path).await {
+        Ok(written) => Ok(written.iter().filter(|b| **b == b'\n').count()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads a journal back. An unterminated last line (a writer that died mid-append) is skipped.
+pub async fn read(path: &Path) -> Result<Vec<WorkStationEntry>> {
+    let text = tokio::fs::read_to_string(path).await?;
+    let complete = text.rfind('\n').map_or("", |end| &text[..end]);
+    if complete.len() + 1 < text.len() {
+        tracing::warn!("Skipping a partially written entry at the end of {}", path.display());
+    }
+
+    complete
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|source| {
+                Error::JournalError {
+                    path: path.to_path_buf(),
+                    line: i + 1,
+                    source,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Workbooks among `files` with a journal (or an interrupted compaction) next to them.
+pub fn journaled_workbooks(files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut workbooks: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| {
+            let name = file.file_name()?.to_str()?;
+            let workbook = name
+                .strip_suffix(&format!(".{JOURNAL_EXT}"))
+                .or_else(|| name.strip_suffix(&format!(".{COMPACTING_EXT}")))?;
+            Some(file.with_file_name(workbook))
+        })
+        .collect();
+    workbooks.sort();
+    workbooks.dedup();
+    workbooks
+}
@@ -0,0 +1,382 @@
+//! Advisory lock files, so machines sharing a workbook take turns at the read-modify-write.
+//! A `<file>.lock` created exclusively holds who owns it; owners that never released it expire.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::prelude::{Error, Result};
+
+/// `[lock]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockConfig {
+    /// Give up (and leave the work for a later run) after waiting this long.
+    pub wait_secs:  u64,
+    /// A lock older than this is assumed abandoned, eg: the machine lost power mid-write.
+    pub stale_secs: u64,
+    /// How often a waiting process checks the lock again.
+    pub poll_ms:    u64,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            wait_secs:  30,
+            stale_secs: 120,
+            poll_ms:    250,
+        }
+    }
+}
+
+/// Contents of the lock file, for whoever has to work out why a workbook is stuck.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid:         u32,
+    pub host:        String,
+    pub user:        String,
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        Self {
+            pid:         std::process::id(),
+            host:        env_or_unknown(&["COMPUTERNAME", "HOSTNAME"]),
+            user:        env_or_unknown(&["USERNAME", "USER"]),
+            acquired_at: Utc::now(),
+        }
+    }
+}
+
+fn env_or_unknown(names: &[&str]) -> String {
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Held until dropped, which removes the lock file (unless someone has since broken it as stale).
+#[derive(Debug)]
+pub struct FileLock {
+    path:  PathBuf,
+    owner: String,
+}
+
+impl FileLock {
+    /// Locks `target` via `<target>.lock`, waiting up to `wait_secs` for the current owner.
+    pub async fn acquire(target: &Path, config: &LockConfig) -> Result<Self> {
+        let mut path = target.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        let wait = Duration::from_secs(config.wait_secs);
+        let stale = Duration::from_secs(config.stale_secs);
+        let started = tokio::time::Instant::now();
+        // Measured once, the first time someone else holds the lock
+        let mut clock_skew = None;
+
+        loop {
+            let owner =
+                serde_json::to_string(&LockOwner::current()).map_err(|e| Error::Generic(e.to_string()))?;
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(mut file) => {
+                    file.write_all(owner.as_bytes()).await?;
+                    file.flush().await?;
+                    return Ok(Self { path, owner });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            let offset = match clock_skew {
+                Some(offset) => offset,
+                None => *clock_skew.insert(clock_offset(&path).await),
+            };
+            // Gone by now (released, or its age unreadable) reads as an unknown owner, it's retried
+            // after the poll like any other
+            let holder = current_holder(&path, offset).await;
+            if let Some(holder) = holder.as_ref().filter(|holder| holder.age >= stale) {
+                tracing::warn!("Breaking stale lock {} held by {}", path.display(), holder.describe());
+                // Only a lock we broke ourselves is worth an immediate retry, one that's being broken
+                // by someone else is waited for like a held one
+                if break_stale(&path, &holder.contents, offset, stale).await? {
+                    continue;
+                }
+            }
+
+            if started.elapsed() >= wait {
+                return Err(Error::LockTimeoutError {
+                    path,
+                    owner: holder.map_or_else(|| "an unknown owner".to_string(), |holder| holder.describe()),
+                    waited: wait,
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(config.poll_ms)).await;
+        }
+    }
+}
+
+/// The lock file as a waiter found it.
+struct Holder {
+    /// On the share's clock, since the file was last written.
+    age:      Duration,
+    contents: String,
+}
+
+impl Holder {
+    fn describe(&self) -> String {
+        match serde_json::from_str::<LockOwner>(&self.contents) {
+            Ok(owner) => {
+                format!("pid {} on {} ({}) since {}", owner.pid, owner.host, owner.user, owner.acquired_at)
+            }
+            // Still being written, or mangled
+            Err(_) => "an unknown owner".to_string(),
+        }
+    }
+}
+
+// How long the lock file has sat unchanged, `None` once it's gone.
+async fn current_holder(path: &Path, offset: TimeDelta) -> Option<Holder> {
+    // Contents first: should the lock change hands in between, the age is the new (fresh) one's rather
+    // than the stale one's next to the new owner's contents
+    let contents = tokio::fs::read_to_string(path).await.unwrap_or_default();
+    let age = file_age(path, offset).await?;
+    Some(Holder { age, contents })
+}
+
+// Aged by the modified time the share stamped on it, so the owner's clock (`acquired_at`) never comes
+// into it. `offset` is the share's clock minus ours.
+async fn file_age(path: &Path, offset: TimeDelta) -> Option<Duration> {
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    let share_now = DateTime::<Utc>::from(SystemTime::now()) + offset;
+    Some(
+        (share_now - DateTime::<Utc>::from(modified))
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+// The share's clock minus ours: writes a probe file next to `lock` and reads back the modified time
+// it got. Taken as 0 (our clock is the share's) if the probe fails.
+async fn clock_offset(lock: &Path) -> TimeDelta {
+    let probe = sibling(lock, "probe");
+    let before = DateTime::<Utc>::from(SystemTime::now());
+    let stamped = match tokio::fs::write(&probe, b"").await {
+        Ok(()) => tokio::fs::metadata(&probe).await.and_then(|meta| meta.modified()),
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&probe).await;
+
+    match stamped {
+        Ok(stamped) => DateTime::<Utc>::from(stamped) - before,
+        Err(e) => {
+            tracing::warn!("Couldn't read the share's clock next to {}: {}", lock.display(), e);
+            TimeDelta::zero()
+        }
+    }
+}
+
+// Removes the lock judged stale, if it still holds `stale_contents`, `true` if this call removed it.
+// Waiters breaking it at the same time take turns through `<lock>.break`, so none of them can remove
+// the lock another one took after breaking it first.
+async fn break_stale(path: &Path, stale_contents: &str, offset: TimeDelta, stale: Duration) -> Result<bool> {
+    let mut guard = path.as_os_str().to_owned();
+    guard.push(".break");
+    let guard = PathBuf::from(guard);
+
+    match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&guard)
+        .await
+    {
+        Ok(_) => {}
+        // Someone else is breaking it, unless they died doing so (it only takes a moment)
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            if file_age(&guard, offset).await.is_some_and(|age| age >= stale) {
+                let _ = tokio::fs::remove_file(&guard).await;
+            }
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let broken = move_aside(path, stale_contents).await;
+    if let Err(e) = tokio::fs::remove_file(&guard).await {
+        tracing::warn!("Couldn't remove {}: {}", guard.display(), e);
+    }
+    broken
+}
+
+// Moves the lock out of the way under a name only we know, then checks it really is the stale one.
+// Only its owner turning out to be alive and releasing it just now can make it something else, that
+// lock goes straight back. `true` if the stale lock is gone.
+async fn move_aside(path: &Path, stale_contents: &str) -> Result<bool> {
+    // Broken (or released and taken) since it was judged stale
+    if tokio::fs::read_to_string(path).await.ok().as_deref() != Some(stale_contents) {
+        return Ok(false);
+    }
+
+    let aside = sibling(path, "stale");
+    match tokio::fs::rename(path, &aside).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    }
+
+    let taken = tokio::fs::read_to_string(&aside).await?;
+    let was_stale = taken == stale_contents;
+    if !was_stale {
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await
+        {
+            Ok(mut file) => {
+                file.write_all(taken.as_bytes()).await?;
+                file.flush().await?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                tracing::warn!("Lock {} changed hands while breaking it", path.display());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if let Err(e) = tokio::fs::remove_file(&aside).await {
+        tracing::warn!("Couldn't remove {}: {}", aside.display(), e);
+    }
+    Ok(was_stale)
+}
+
+// `<lock>.<random>.<kind>`, private to this process.
+fn sibling(lock: &Path, kind: &str) -> PathBuf {
+    let mut path = lock.as_os_str().to_owned();
+    path.push(format!(".{}.{kind}", Uuid::new_v4().simple()));
+    PathBuf::from(path)
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Only remove our own lock, a slow holder may have been judged stale and replaced
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) if contents == self.owner => {
+                if let Err(e) = std::fs::remove_file(&self.path) {
+                    tracing::warn!("Couldn't remove lock {}: {}", self.path.display(), e);
+                }
+            }
+            Ok(_) => tracing::warn!("Lock {} was taken over while held", self.path.display()),
+            Err(e) => tracing::warn!("Couldn't read lock {} to release it: {}", self.path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn config(wait_secs: u64, stale_secs: u64) -> LockConfig {
+        LockConfig {
+            wait_secs,
+            stale_secs,
+            poll_ms: 10,
+        }
+    }
+
+    fn backdate(path: &Path, secs: u64) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn held_lock_times_out_then_frees_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("book.xlsx");
+
+        let held = FileLock::acquire(&target, &config(0, 60)).await.unwrap();
+        let err = FileLock::acquire(&target, &config(0, 60)).await.unwrap_err();
+        assert!(matches!(err, Error::LockTimeoutError { .. }), "{err}");
+
+        drop(held);
+        FileLock::acquire(&target, &config(0, 60)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_lock_is_broken_by_its_age_on_the_share() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("book.xlsx");
+        let lock = dir.path().join("book.xlsx.lock");
+        // Owner claims a fresh lock, only the file's own mtime says otherwise
+        let owner = LockOwner {
+            acquired_at: Utc::now() + TimeDelta::hours(1),
+            ..LockOwner::current()
+        };
+        std::fs::write(&lock, serde_json::to_string(&owner).unwrap()).unwrap();
+        backdate(&lock, 120);
+
+        let held = FileLock::acquire(&target, &config(0, 60)).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&lock).unwrap(), held.owner);
+        // Nothing left behind by the breaking
+        drop(held);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn waiters_breaking_a_stale_lock_take_turns() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = Arc::new(dir.path().join("book.xlsx"));
+        let lock = dir.path().join("book.xlsx.lock");
+        std::fs::write(&lock, "crashed").unwrap();
+        backdate(&lock, 120);
+
+        let inside = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let (target, inside) = (target.clone(), inside.clone());
+                tokio::spawn(async move {
+                    let _held = FileLock::acquire(&target, &config(30, 60)).await.unwrap();
+                    assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0, "lock held twice");
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    inside.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(!lock.exists());
+    }
+
+    #[tokio::test]
+    async fn dead_breaker_still_leaves_wait_secs_in_charge() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("book.xlsx");
+        let lock = dir.path().join("book.xlsx.lock");
+        std::fs::write(&lock, "crashed").unwrap();
+        backdate(&lock, 120);
+        // Someone started breaking it and died, their guard only goes stale after `stale_secs`
+        std::fs::write(dir.path().join("book.xlsx.lock.break"), "").unwrap();
+
+        let started = std::time::Instant::now();
+        let err = FileLock::acquire(&target, &config(1, 60)).await.unwrap_err();
+        assert!(matches!(err, Error::LockTimeoutError { .. }), "{err}");
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_secs(1) && waited < Duration::from_secs(3), "{waited:?}");
+        assert_eq!(std::fs::read_to_string(&lock).unwrap(), "crashed");
+    }
+}
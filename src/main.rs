@@ -1,22 +1,48 @@
+#![cfg_attr(test, feature(test))]
+
 mod append;
+mod calendar;
+mod cli;
 mod collect;
+mod collector;
+mod config;
+mod directory;
+mod dn;
 mod error;
 mod executor;
+mod history;
+mod ics;
+mod journal;
+mod lock;
 mod period;
 mod prelude;
+mod provision;
+mod query;
+mod report;
+mod retention;
+mod rotation;
+mod shell;
+mod sink;
+mod spool;
+mod sqlite_sink;
+mod text_sink;
 mod user_entry;
 mod workstation;
-use std::str::FromStr;
+
+#[cfg(test)]
+extern crate test;
 
 use calamine::Data;
 use chrono::{DateTime, Local};
+use clap::Parser;
 use rust_xlsxwriter::worksheet::Worksheet;
 
-use crate::append::append_log;
-use crate::collect::{collect_base_info, collect_hardware, collect_os_info};
-use crate::executor::PsExecutor;
+use crate::cli::{Cli, Command, ConfigCommand};
+use crate::collector::Collectors;
+use crate::config::Config;
 pub use crate::prelude::{Error, Result, W};
-use crate::user_entry::UserEntry;
+use crate::sink::{SinkConfig, build_sinks, prepare_all, sweep_all, write_all};
+use crate::spool::Spool;
 use crate::workstation::WorkStationEntry;
 
 pub trait ExcelLoggable: Sized + Clone {
@@ -24,6 +50,9 @@ pub trait ExcelLoggable: Sized + Clone {
 
     fn write_entry(&self, ws: &mut Worksheet, row: u32) -> Result<()>; // as rust_xlsxwriter::Resultlsxwriter
 
+    /// One string per `COLUMNS` entry, same order. Datetimes are RFC 3339 with the local offset.
+    fn field_values(&self) -> Vec<String>;
+
     fn parse_row(row: &[Data]) -> Option<Self>;
 
     fn excel_date_to_chrono(serial: f64) -> DateTime<Local>;
@@ -37,39 +66,92 @@ pub trait FieldLengsths {
     fn field_lengths(&self) -> Vec<usize>;
 }
 
-// TODO: [customizability] : Move these to be changable via env vars with compile time (literal)
-// fallbacks. Additionally, might consider having a config we read from at program start (on a
-// network drive etc.). These would then need to be `static` instead.
-
-const WORKSHEET_NAME: &str = "Logons";
-const WS_BASE_PATH: &str = r"\\Server\LogonLogger$\Logs\ComputerNEW";
-const USER_BASE_PATH: &str = r"\\Server\LogonLogger$\Logs\UserNEW";
-
 #[tokio::main]
 async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let config = Config::load(cli.config_path.as_deref())?;
+
+    match cli.command.unwrap_or(Command::Log { sweep: false }) {
+        Command::Log { sweep } => log(&config, sweep).await,
+        Command::DryRun => dry_run(&config).await,
+        Command::Query(args) => query::query(&config, args.family, &args.filter(), args.format).await,
+        Command::Report(args) => report::report(&config, args.family, args.from, args.to, args.by).await,
+        Command::Merge(args) => history::merge(&config, args.family, args.inputs, &args.output).await,
+        Command::Validate(args) => history::validate(&config, args.family, args.paths).await,
+        Command::Retention { dry_run } => retention::run(&config, dry_run).await,
+        Command::Config(ConfigCommand::Check) => config_check(&config),
+    }
+}
+
+async fn log(config: &Config, sweep: bool) -> Result<()> {
     let now = Local::now();
-    let executor = PsExecutor::new();
+    let collectors = Collectors::from_config(config)?;
+    let sinks = build_sinks(config)?;
+    prepare_all(&sinks).await;
 
-    // let base_info = collect_base_info(&executor).await?;
-    // let hardware_info = collect_hardware().await?;
-    // let os_info = collect_os_info().await?;
+    // Whatever earlier runs couldn't deliver goes first
+    let mut spool = Spool::from_config(config);
+    if let Some(spool) = spool.as_mut() {
+        spool.drain(&sinks).await;
+    }
 
-    let today = chrono::NaiveDate::from_str(&now.format("%Y-%m-%d").to_string())
-        .map_err(|e| Error::Generic(format!("Failed to format date: {}", e)))?;
+    let (base_info, hardware_info, os_info) = collectors.collect().await?;
 
-    let workstation_log = format!("workstation_log_{today}");
-    let user_log = format!("user_log_{today}");
+    let ws = WorkStationEntry::from((base_info, hardware_info, os_info, now, &config.schedule));
 
-    let (base_info, hardware_info, os_info) =
-        tokio::try_join!(collect_base_info(&executor), collect_hardware(), collect_os_info())?;
+    let written = write_all(&sinks, &ws, spool.as_ref()).await;
+    if sweep {
+        sweep_all(&sinks).await;
+    }
+    written
+}
 
-    let ws = WorkStationEntry::from((base_info, hardware_info, os_info, now));
-    let user_entry = UserEntry::from(ws.clone());
+async fn dry_run(config: &Config) -> Result<()> {
+    let now = Local::now();
+    let (base_info, hardware_info, os_info) = Collectors::from_config(config)?.collect().await?;
+    let ws = WorkStationEntry::from((base_info, hardware_info, os_info, now, &config.schedule));
+
+    let width = WorkStationEntry::COLUMNS
+        .iter()
+        .map(|c| c.len())
+        .max()
+        .unwrap_or(0);
+    for (column, value) in WorkStationEntry::COLUMNS.iter().zip(ws.field_values()) {
+        println!("{column:<width$}  {value}");
+    }
+    Ok(())
+}
 
-    tokio::try_join!(
-        append_log(WS_BASE_PATH, &workstation_log, ws),
-        append_log(USER_BASE_PATH, &user_log, user_entry)
-    )?;
+// `Config::load` has validated it by now, this checks the sinks build and shows where they write.
+fn config_check(config: &Config) -> Result<()> {
+    match &config.source {
+        Some(path) => println!("Config: {}", path.display()),
+        None => println!("Config: none found, using the built-in defaults"),
+    }
 
+    let now = Local::now();
+    let sinks = build_sinks(config)?;
+    for (sink, sink_config) in sinks.iter().zip(SinkConfig::configured(config)) {
+        let target = match (sink_config.files(config)?, &sink_config) {
+            (Some(files), _) => files.path(&now),
+            (None, SinkConfig::Sqlite { path, .. }) => config.resolve_relative(path),
+            (None, _) => continue,
+        };
+        println!("Sink {}: {}", sink.id(), target.display());
+    }
+
+    match Spool::from_config(config) {
+        Some(spool) => println!("Spool: {}", spool.dir().display()),
+        None => println!("Spool: disabled"),
+    }
+    println!("Config OK");
     Ok(())
 }
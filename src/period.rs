@@ -1,19 +1,34 @@
-use chrono::{DateTime, NaiveTime};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike, Weekday};
+use serde::Deserialize;
+
+use crate::calendar::{DayKind, TermCalendar};
+use crate::config::deserialize_date;
+use crate::prelude::{Error, Result};
+
+const MINUTES_PER_DAY: usize = 24 * 60;
+
+/// Name the top-level `timetable` is registered under inside a [`Schedule`].
+pub const DEFAULT_TIMETABLE: &str = "default";
+
+#[derive(Debug, Clone)]
 pub struct TimePeriod {
     start:          NaiveTime,
     end:            NaiveTime,
     wraps_midnight: bool,
-    name:           &'static str,
+    name:           String,
 }
 
 impl TimePeriod {
-    pub const fn new(start: NaiveTime, end: NaiveTime, wraps_midnight: bool, name: &'static str) -> Self {
+    /// A period whose `end` is at or before its `start` wraps past midnight (eg: "After Hours").
+    pub fn new(start: NaiveTime, end: NaiveTime, name: impl Into<String>) -> Self {
         Self {
             start,
             end,
-            wraps_midnight,
-            name,
+            wraps_midnight: end <= start,
+            name: name.into(),
         }
     }
 
@@ -30,31 +45,580 @@ pub const fn hms(h: u32, m: u32) -> chrono::NaiveTime {
     chrono::NaiveTime::from_hms_opt(h, m, 0).unwrap()
 }
 
-// TODO: [trait] : We should do this via Trait + blank structs
-// Which will be better as adding a 'new' period would be easier via ( new struct + impl Trait )
-pub const PERIODS: [TimePeriod; 9] = [
-    TimePeriod::new(hms(5, 0), hms(8, 45), false, "Before School"),
-    TimePeriod::new(hms(8, 45), hms(8, 55), false, "Form"),
-    TimePeriod::new(hms(8, 55), hms(10, 5), false, "Period 1"),
-    TimePeriod::new(hms(10, 5), hms(11, 15), false, "Period 2"),
-    TimePeriod::new(hms(11, 15), hms(11, 55), false, "Morning Tea"),
-    TimePeriod::new(hms(11, 55), hms(13, 5), false, "Period 3"),
-    TimePeriod::new(hms(13, 5), hms(13, 45), false, "Second Lunch"),
-    TimePeriod::new(hms(13, 45), hms(14, 55), false, "Period 4"),
-    TimePeriod::new(hms(14, 55), hms(5, 0), true, "After Hours"),
+// Compiled-in fallback, used when the config doesn't provide a timetable.
+const DEFAULT_PERIODS: [(NaiveTime, NaiveTime, &str); 9] = [
+    (hms(5, 0), hms(8, 45), "Before School"),
+    (hms(8, 45), hms(8, 55), "Form"),
+    (hms(8, 55), hms(10, 5), "Period 1"),
+    (hms(10, 5), hms(11, 15), "Period 2"),
+    (hms(11, 15), hms(11, 55), "Morning Tea"),
+    (hms(11, 55), hms(13, 5), "Period 3"),
+    (hms(13, 5), hms(13, 45), "Second Lunch"),
+    (hms(13, 45), hms(14, 55), "Period 4"),
+    (hms(14, 55), hms(5, 0), "After Hours"),
 ];
 
+/// A validated set of periods, every minute of the day maps to exactly one of them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "TimetableDef")]
+pub struct Timetable {
+    periods: Vec<TimePeriod>,
+}
+
+impl Default for Timetable {
+    fn default() -> Self {
+        let periods = DEFAULT_PERIODS
+            .iter()
+            .map(|(start, end, name)| TimePeriod::new(*start, *end, *name))
+            .collect();
+        Self { periods }
+    }
+}
+
+impl Timetable {
+    pub fn new(periods: Vec<TimePeriod>) -> Result<Self> {
+        validate(&periods)?;
+        Ok(Self { periods })
+    }
+
+    /// Loads a standalone timetable file, the format is picked from the extension (`.json`, else TOML).
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            Error::TimetableError(format!("Failed to read timetable {}: {}", path.display(), e))
+        })?;
+
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        let def: TimetableDef = if is_json {
+            serde_json::from_str(&raw)
+                .map_err(|e| Error::TimetableError(format!("Invalid timetable {}: {}", path.display(), e)))?
+        } else {
+            toml::from_str(&raw)
+                .map_err(|e| Error::TimetableError(format!("Invalid timetable {}: {}", path.display(), e)))?
+        };
+
+        Self::try_from(def)
+    }
+
+    pub fn period_at(&self, time: &NaiveTime) -> Option<&TimePeriod> {
+        self.periods.iter().find(|p| p.contains(time))
+    }
+}
+
+// On-disk shape of a timetable, shared by the inline `[timetable]` table and standalone files.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimetableDef {
+    periods: Vec<TimePeriodDef>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimePeriodDef {
+    name:  String,
+    start: String,
+    end:   String,
+}
+
+impl TryFrom<TimetableDef> for Timetable {
+    type Error = Error;
+
+    fn try_from(def: TimetableDef) -> Result<Self> {
+        let periods = def
+            .periods
+            .into_iter()
+            .map(|p| {
+                if p.name.trim().is_empty() {
+                    return Err(Error::TimetableError("Period names must not be empty".to_string()));
+                }
+                let start = parse_time(&p.name, &p.start)?;
+                let end = parse_time(&p.name, &p.end)?;
+                Ok(TimePeriod::new(start, end, p.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(periods)
+    }
+}
+
+fn parse_time(period: &str, raw: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(raw, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(raw, "%H:%M:%S"))
+        .map_err(|e| Error::TimetableError(format!("Period \"{period}\": invalid time \"{raw}\" ({e})")))
+        .and_then(|t| {
+            // Validation works per-minute, sub-minute boundaries could hide a gap
+            if t.second() != 0 {
+                return Err(Error::TimetableError(format!(
+                    "Period \"{period}\": \"{raw}\" must fall on a whole minute"
+                )));
+            }
+            Ok(t)
+        })
+}
+
+// Walks every minute of the day and checks exactly one period claims it.
+fn validate(periods: &[TimePeriod]) -> Result<()> {
+    if periods.is_empty() {
+        return Err(Error::TimetableError("Timetable has no periods".to_string()));
+    }
+
+    let mut gap_start: Option<NaiveTime> = None;
+
+    for minute in 0..MINUTES_PER_DAY {
+        let time = hms((minute / 60) as u32, (minute % 60) as u32);
+        let mut owners = periods.iter().filter(|p| p.contains(&time));
+
+        match (owners.next(), owners.next()) {
+            (Some(_), None) => {
+                if let Some(start) = gap_start.take() {
+                    return Err(Error::TimetableError(format!(
+                        "Timetable has a gap from {} to {}",
+                        start.format("%H:%M"),
+                        time.format("%H:%M")
+                    )));
+                }
+            }
+            (Some(a), Some(b)) => {
+                return Err(Error::TimetableError(format!(
+                    "Periods \"{}\" and \"{}\" overlap at {}",
+                    a.name,
+                    b.name,
+                    time.format("%H:%M")
+                )));
+            }
+            (None, _) => {
+                gap_start.get_or_insert(time);
+            }
+        }
+    }
+
+    if let Some(start) = gap_start {
+        return Err(Error::TimetableError(format!(
+            "Timetable has a gap from {} to midnight",
+            start.format("%H:%M")
+        )));
+    }
+
+    Ok(())
+}
+
+/// `[schedule]` as written in the config, turned into a [`Schedule`] by [`Schedule::build`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleDef {
+    /// Extra named timetables, defined inline.
+    pub timetables:      BTreeMap<String, Timetable>,
+    /// Extra named timetables, loaded from standalone files.
+    pub timetable_files: BTreeMap<String, PathBuf>,
+    pub rotation:        Option<RotationDef>,
+    /// Checked in order, the first match wins. Nothing matching means the default timetable.
+    pub variants:        Vec<VariantDef>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotationDef {
+    /// Any date in the first week of the rotation, the week is anchored to its Monday.
+    #[serde(deserialize_with = "deserialize_date")]
+    pub start: NaiveDate,
+    /// One label per week of the cycle, eg: `["A", "B"]`.
+    pub weeks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantDef {
+    pub timetable: String,
+    /// Empty means every day.
+    #[serde(default)]
+    pub weekdays:  Vec<Weekday>,
+    /// Rotation week labels, empty means every week.
+    #[serde(default)]
+    pub weeks:     Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Rotation {
+    anchor: NaiveDate,
+    weeks:  Vec<String>,
+}
+
+impl Rotation {
+    fn week_of(&self, date: NaiveDate) -> &str {
+        let weeks_since = (date - self.anchor).num_days().div_euclid(7);
+        let idx = weeks_since.rem_euclid(self.weeks.len() as i64) as usize;
+        &self.weeks[idx]
+    }
+}
+
+/// Picks the timetable for a given date: per-weekday variants on top of an optional N-week rotation,
+/// with the term calendar taking precedence on holidays and staff days.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    timetables: BTreeMap<String, Timetable>,
+    rotation:   Option<Rotation>,
+    variants:   Vec<VariantDef>,
+    calendar:   TermCalendar,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            timetables: BTreeMap::from([(DEFAULT_TIMETABLE.to_string(), Timetable::default())]),
+            rotation:   None,
+            variants:   vec![],
+            calendar:   TermCalendar::default(),
+        }
+    }
+}
+
+/// The period a point in time falls in, plus which schedule variant it was resolved against.
+#[derive(Debug, Clone)]
+pub struct ResolvedPeriod {
+    pub name:    String,
+    pub variant: String,
+}
+
+impl Schedule {
+    /// `resolve` maps `timetable_files` entries to real paths (the config resolves them relative to itself).
+    pub fn build(
+        default: Timetable,
+        def: &ScheduleDef,
+        calendar: TermCalendar,
+        resolve: impl Fn(&Path) -> PathBuf,
+    ) -> Result<Self> {
+        let mut timetables = BTreeMap::from([(DEFAULT_TIMETABLE.to_string(), default)]);
+
+        for (name, timetable) in &def.timetables {
+            insert_unique(&mut timetables, name, timetable.clone())?;
+        }
+        for (name, path) in &def.timetable_files {
+            insert_unique(&mut timetables, name, Timetable::from_path(&resolve(path))?)?;
+        }
+
+        let rotation = match &def.rotation {
+            Some(r) if r.weeks.is_empty() => {
+                return Err(Error::TimetableError("`schedule.rotation.weeks` must not be empty".to_string()));
+            }
+            Some(r) => {
+                let anchor = r.start - chrono::Days::new(r.start.weekday().num_days_from_monday() as u64);
+                Some(Rotation {
+                    anchor,
+                    weeks: r.weeks.clone(),
+                })
+            }
+            None => None,
+        };
+
+        for variant in &def.variants {
+            if !timetables.contains_key(&variant.timetable) {
+                return Err(Error::TimetableError(format!(
+                    "Schedule variant refers to unknown timetable \"{}\"",
+                    variant.timetable
+                )));
+            }
+            if let Some(week) = variant
+                .weeks
+                .iter()
+                .find(|w| !rotation.as_ref().is_some_and(|r| r.weeks.contains(w)))
+            {
+                return Err(Error::TimetableError(format!(
+                    "Schedule variant for \"{}\" refers to week \"{}\", which is not part of `schedule.rotation`",
+                    variant.timetable, week
+                )));
+            }
+        }
+
+        Ok(Self {
+            timetables,
+            rotation,
+            variants: def.variants.clone(),
+            calendar,
+        })
+    }
+
+    pub fn calendar(&self) -> &TermCalendar {
+        &self.calendar
+    }
+
+    /// The timetable in effect on `date` and the label of the variant that selected it.
+    pub fn timetable_for(&self, date: NaiveDate) -> (&Timetable, String) {
+        let week = self.rotation.as_ref().map(|r| r.week_of(date));
+        let weekday = date.weekday();
+
+        let name = self
+            .variants
+            .iter()
+            .find(|v| {
+                (v.weekdays.is_empty() || v.weekdays.contains(&weekday))
+                    && (v.weeks.is_empty() || week.is_some_and(|w| v.weeks.iter().any(|vw| vw == w)))
+            })
+            .map(|v| v.timetable.as_str())
+            .unwrap_or(DEFAULT_TIMETABLE);
+
+        let label = match week {
+            Some(week) => format!("Week {week} - {name}"),
+            None => name.to_string(),
+        };
+
+        // `build` guarantees every variant (and the default) points at a known timetable
+        (&self.timetables[name], label)
+    }
+}
+
+fn insert_unique(
+    timetables: &mut BTreeMap<String, Timetable>,
+    name: &str,
+    timetable: Timetable,
+) -> Result<()> {
+    if timetables.insert(name.to_string(), timetable).is_some() {
+        return Err(Error::TimetableError(format!("Timetable \"{name}\" is defined more than once")));
+    }
+    Ok(())
+}
+
 pub(crate) fn get_current_period(
     now: &DateTime<chrono::Local>,
-    periods: &[TimePeriod],
-) -> Result<String, String> {
+    schedule: &Schedule,
+) -> std::result::Result<ResolvedPeriod, String> {
     let current = now.time();
 
-    for period in periods.iter() {
-        if period.contains(&current) {
-            return Ok(period.name.to_string());
+    match schedule.calendar.day_kind(now.date_naive()) {
+        DayKind::School => {}
+        DayKind::Holiday { label, name } | DayKind::StaffDay { label, name } => {
+            return Ok(ResolvedPeriod {
+                name:    label.to_string(),
+                variant: name.to_string(),
+            });
+        }
+    }
+
+    let (timetable, variant) = schedule.timetable_for(now.date_naive());
+
+    timetable
+        .period_at(&current)
+        .map(|p| {
+            ResolvedPeriod {
+                name: p.name.clone(),
+                variant,
+            }
+        })
+        .ok_or_else(|| format!("Current time {} does not fall into any defined period", current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timetable(periods: &[(&str, &str, &str)]) -> Result<Timetable> {
+        let periods = periods
+            .iter()
+            .map(|(start, end, name)| {
+                TimePeriod::new(parse_time(name, start).unwrap(), parse_time(name, end).unwrap(), *name)
+            })
+            .collect();
+        Timetable::new(periods)
+    }
+
+    fn rejected(periods: &[(&str, &str, &str)]) -> String {
+        match timetable(periods) {
+            Err(Error::TimetableError(message)) => message,
+            other => panic!("expected a timetable error, got {other:?}"),
         }
     }
 
-    Err(format!("Current time {} does not fall into any defined period", current))
+    fn name_at(timetable: &Timetable, time: &str) -> String {
+        let time = NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        timetable.period_at(&time).unwrap().name.clone()
+    }
+
+    #[test]
+    fn compiled_in_timetable_is_valid() {
+        assert!(Timetable::new(Timetable::default().periods).is_ok());
+    }
+
+    #[test]
+    fn period_wrapping_midnight_covers_both_ends_of_the_day() {
+        let day_night = timetable(&[("06:00", "18:00", "Day"), ("18:00", "06:00", "Night")]).unwrap();
+        assert_eq!(name_at(&day_night, "05:59"), "Night");
+        assert_eq!(name_at(&day_night, "06:00"), "Day");
+        assert_eq!(name_at(&day_night, "17:59"), "Day");
+        assert_eq!(name_at(&day_night, "23:59"), "Night");
+        assert_eq!(name_at(&day_night, "00:00"), "Night");
+
+        // Ending where it starts wraps all the way round
+        let all_day = timetable(&[("07:30", "07:30", "Any")]).unwrap();
+        assert_eq!(name_at(&all_day, "07:29"), "Any");
+        assert_eq!(name_at(&all_day, "07:30"), "Any");
+    }
+
+    #[test]
+    fn overlapping_periods_are_rejected() {
+        assert_eq!(
+            rejected(&[("00:00", "12:00", "Morning"), ("11:30", "00:00", "Afternoon")]),
+            "Periods \"Morning\" and \"Afternoon\" overlap at 11:30"
+        );
+        // Overlapping through the wrap
+        assert_eq!(
+            rejected(&[("22:00", "07:00", "Night"), ("06:00", "22:00", "Day")]),
+            "Periods \"Night\" and \"Day\" overlap at 06:00"
+        );
+        assert!(
+            rejected(&[("00:00", "00:00", "Any"), ("09:00", "10:00", "P1")]).contains("overlap at 09:00")
+        );
+    }
+
+    #[test]
+    fn gaps_are_rejected() {
+        assert_eq!(
+            rejected(&[("00:00", "08:00", "Early"), ("09:00", "00:00", "Late")]),
+            "Timetable has a gap from 08:00 to 09:00"
+        );
+        assert_eq!(rejected(&[("00:00", "20:00", "Day")]), "Timetable has a gap from 20:00 to midnight");
+        // The wrapping period stops short of the first one
+        assert_eq!(
+            rejected(&[("08:00", "17:00", "Day"), ("17:00", "07:00", "Night")]),
+            "Timetable has a gap from 07:00 to 08:00"
+        );
+    }
+
+    #[test]
+    fn empty_timetable_is_rejected() {
+        assert_eq!(rejected(&[]), "Timetable has no periods");
+        assert!(toml::from_str::<Timetable>("periods = []").is_err());
+    }
+
+    #[test]
+    fn definitions_are_checked_as_they_load() {
+        let def = |periods: &str| toml::from_str::<Timetable>(&format!("periods = [{periods}]"));
+
+        assert!(def(r#"{ name = "Day", start = "00:00", end = "00:00" }"#).is_ok());
+        assert!(def(r#"{ name = "Day", start = "00:00:00", end = "00:00:30" }"#).is_err());
+        assert!(def(r#"{ name = "Day", start = "24:00", end = "00:00" }"#).is_err());
+        assert!(def(r#"{ name = " ", start = "00:00", end = "00:00" }"#).is_err());
+        assert!(def(r#"{ name = "Day", start = "00:00", end = "00:00", room = "A1" }"#).is_err());
+    }
+
+    #[test]
+    fn standalone_files_pick_the_format_from_the_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("exams.JSON");
+        std::fs::write(&json, r#"{"periods": [{"name": "Exam", "start": "09:00", "end": "09:00"}]}"#)
+            .unwrap();
+        assert_eq!(name_at(&Timetable::from_path(&json).unwrap(), "12:00"), "Exam");
+
+        let toml = dir.path().join("exams.toml");
+        std::fs::write(&toml, "[[periods]]\nname = \"Exam\"\nstart = \"09:00\"\nend = \"10:00\"\n").unwrap();
+        let message = Timetable::from_path(&toml).unwrap_err().to_string();
+        // The walk starts at midnight, so a gap across it is reported from there
+        assert!(message.contains("gap from 00:00 to 09:00"), "{message}");
+    }
+
+    fn schedule(def: &str) -> Result<Schedule> {
+        let def = format!(
+            r#"
+            [timetables.sport]
+            periods = [{{ name = "Sport", start = "00:00", end = "00:00" }}]
+            [timetables.short]
+            periods = [{{ name = "Short Day", start = "00:00", end = "00:00" }}]
+            {def}
+            "#
+        );
+        let def: ScheduleDef = toml::from_str(&def).unwrap();
+        Schedule::build(Timetable::default(), &def, TermCalendar::default(), Path::to_path_buf)
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn label_on(schedule: &Schedule, day: &str) -> String {
+        schedule.timetable_for(date(day)).1
+    }
+
+    // Starts on Wednesday 2026-02-04, so week A runs from Monday 2026-02-02
+    const ABC: &str = r#"
+        [rotation]
+        start = "2026-02-04"
+        weeks = ["A", "B", "C"]
+    "#;
+
+    #[test]
+    fn rotation_is_anchored_to_the_monday_and_cycles() {
+        let schedule = schedule(ABC).unwrap();
+        assert_eq!(label_on(&schedule, "2026-02-02"), "Week A - default");
+        assert_eq!(label_on(&schedule, "2026-02-08"), "Week A - default");
+        assert_eq!(label_on(&schedule, "2026-02-09"), "Week B - default");
+        assert_eq!(label_on(&schedule, "2026-02-16"), "Week C - default");
+        // Last day of the cycle, then back to the first week
+        assert_eq!(label_on(&schedule, "2026-02-22"), "Week C - default");
+        assert_eq!(label_on(&schedule, "2026-02-23"), "Week A - default");
+        assert_eq!(label_on(&schedule, "2026-12-28"), "Week C - default");
+    }
+
+    #[test]
+    fn weeks_before_the_anchor_count_backwards_through_the_cycle() {
+        let schedule = schedule(ABC).unwrap();
+        assert_eq!(label_on(&schedule, "2026-02-01"), "Week C - default");
+        assert_eq!(label_on(&schedule, "2026-01-26"), "Week C - default");
+        assert_eq!(label_on(&schedule, "2026-01-25"), "Week B - default");
+        assert_eq!(label_on(&schedule, "2026-01-19"), "Week B - default");
+        assert_eq!(label_on(&schedule, "2026-01-12"), "Week A - default");
+        assert_eq!(label_on(&schedule, "2025-12-29"), "Week B - default");
+    }
+
+    #[test]
+    fn first_matching_variant_wins_over_the_default() {
+        let schedule = schedule(&format!(
+            r#"
+            {ABC}
+            [[variants]]
+            timetable = "sport"
+            weekdays = ["Wed"]
+            weeks = ["B"]
+            [[variants]]
+            timetable = "short"
+            weekdays = ["Wed", "Fri"]
+            "#
+        ))
+        .unwrap();
+
+        // Week A Wednesday: only the second variant matches
+        assert_eq!(label_on(&schedule, "2026-02-04"), "Week A - short");
+        // Week B Wednesday: both match, the first listed wins
+        let (timetable, label) = schedule.timetable_for(date("2026-02-11"));
+        assert_eq!(label, "Week B - sport");
+        assert_eq!(timetable.period_at(&hms(9, 0)).unwrap().name, "Sport");
+        assert_eq!(label_on(&schedule, "2026-02-13"), "Week B - short");
+        // Nothing for Thursdays
+        let (timetable, label) = schedule.timetable_for(date("2026-02-12"));
+        assert_eq!(label, "Week B - default");
+        assert_eq!(timetable.period_at(&hms(9, 0)).unwrap().name, "Period 1");
+    }
+
+    #[test]
+    fn without_a_rotation_the_label_is_the_timetable_name() {
+        let schedule = schedule("[[variants]]\ntimetable = \"sport\"\nweekdays = [\"Mon\"]").unwrap();
+        assert_eq!(label_on(&schedule, "2026-02-02"), "sport");
+        assert_eq!(label_on(&schedule, "2026-02-03"), "default");
+    }
+
+    #[test]
+    fn schedules_referring_to_unknown_names_are_rejected() {
+        let message = |def: &str| schedule(def).unwrap_err().to_string();
+
+        assert!(message("[[variants]]\ntimetable = \"exam\"").contains("unknown timetable \"exam\""));
+        assert!(message("[[variants]]\ntimetable = \"sport\"\nweeks = [\"A\"]").contains("week \"A\""));
+        assert!(
+            message(&format!("{ABC}\n[[variants]]\ntimetable = \"sport\"\nweeks = [\"D\"]"))
+                .contains("week \"D\"")
+        );
+        assert!(message("[rotation]\nstart = \"2026-02-04\"\nweeks = []").contains("must not be empty"));
+        assert!(
+            message("[timetables.default]\nperiods = [{ name = \"X\", start = \"00:00\", end = \"00:00\" }]")
+                .contains("defined more than once")
+        );
+    }
 }
@@ -20,17 +20,19 @@ pub fn time<T>(t: &str, f: impl FnOnce() -> T) -> T {
     r
 }
 
-#[allow(dead_code)]
+// Directory the binary lives in (release), or the working dir while developing.
 #[cfg(not(debug_assertions))]
-fn current_path() -> Result<std::path::PathBuf> {
+pub(crate) fn current_path() -> Result<std::path::PathBuf> {
     std::env::current_exe()
         .map_err(|e| e.to_string())
-        .map_err(Error::Generic)
+        .map_err(Error::Generic)?
+        .parent()
+        .map(std::path::Path::to_path_buf)
+        .ok_or_else(|| Error::Generic("Executable has no parent directory".to_string()))
 }
 
-#[allow(dead_code)]
 #[cfg(debug_assertions)]
-fn current_path() -> Result<std::path::PathBuf> {
+pub(crate) fn current_path() -> Result<std::path::PathBuf> {
     std::env::current_dir()
         .map_err(|e| e.to_string())
         .map_err(Error::Generic)
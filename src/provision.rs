@@ -0,0 +1,220 @@
+//! Log directories: where an entry's files go under a sink's base path, and making sure that
+//! directory exists and is writable before anything is written to it.
+
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::prelude::{Error, Result};
+
+const TOKENS: &[&str] = &["{year}", "{month}", "{day}", "{week}"];
+
+/// Subdirectory template under a sink's base path, eg: `{year}/{month}` puts the files for
+/// 2026-10-17 in `<base>/2026/10/`. `{week}` is the ISO week (`W42`), and with it `{year}` is the
+/// ISO week's year so `{year}/{week}` never puts 2025-12-29 in `2025/W01`. The empty template is flat.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DirLayout(String);
+
+impl TryFrom<String> for DirLayout {
+    type Error = String;
+
+    fn try_from(template: String) -> std::result::Result<Self, Self::Error> {
+        let path = Path::new(&template);
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!(
+                "layout \"{template}\" must be a relative path without `..`, it goes under the base path"
+            ));
+        }
+
+        let mut rest = TOKENS
+            .iter()
+            .fold(template.clone(), |rest, token| rest.replace(token, ""));
+        rest.retain(|c| !matches!(c, '/' | '\\'));
+        if rest.contains(['{', '}']) {
+            return Err(format!(
+                "layout \"{template}\" has an unknown placeholder, expected any of {}",
+                TOKENS.join(", ")
+            ));
+        }
+        Ok(Self(template))
+    }
+}
+
+impl DirLayout {
+    /// The directory for the file whose rotation period starts on `date`, under `base_path`.
+    pub fn dir(&self, base_path: &Path, date: NaiveDate) -> PathBuf {
+        if self.0.is_empty() {
+            return base_path.to_path_buf();
+        }
+        let week = date.iso_week();
+        let year = if self.0.contains("{week}") {
+            week.year()
+        } else {
+            date.year()
+        };
+        let rendered = self
+            .0
+            .replace("{year}", &format!("{year:04}"))
+            .replace("{month}", &format!("{:02}", date.month()))
+            .replace("{day}", &format!("{:02}", date.day()))
+            .replace("{week}", &format!("W{:02}", week.week()));
+        rendered
+            .split(['/', '\\'])
+            .filter(|part| !part.is_empty())
+            .fold(base_path.to_path_buf(), |dir, part| dir.join(part))
+    }
+}
+
+/// Creates log directories (the whole hierarchy, fine if it already exists) and checks they can be
+/// written to. Each directory is only checked once per run.
+#[derive(Debug, Default)]
+pub struct Provisioner {
+    ready: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl Provisioner {
+    pub async fn ensure(&self, dir: &Path) -> Result<()> {
+        let mut ready = self.ready.lock().await;
+        if ready.contains(dir) {
+            return Ok(());
+        }
+
+        tokio::fs::create_dir_all(dir).await.map_err(|source| {
+            Error::ProvisionError {
+                path: dir.to_path_buf(),
+                source,
+            }
+        })?;
+        probe_write(dir).await.map_err(|source| {
+            Error::ProvisionError {
+                path: dir.to_path_buf(),
+                source,
+            }
+        })?;
+
+        ready.insert(dir.to_path_buf());
+        Ok(())
+    }
+}
+
+// Share permissions often allow listing but not creating files, find out now rather than mid-write.
+async fn probe_write(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".write_probe-{}", std::process::id()));
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .await?;
+    file.write_all(b"logon_logger").await?;
+    drop(file);
+    tokio::fs::remove_file(&probe).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(template: &str) -> std::result::Result<DirLayout, String> {
+        DirLayout::try_from(template.to_string())
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn layouts_stay_under_the_base_path() {
+        assert!(layout("").is_ok());
+        assert!(layout("{year}/{month}/{day}").is_ok());
+        assert!(layout("archive-{year}\\{week}").is_ok());
+
+        assert!(layout("../{year}").unwrap_err().contains("without `..`"));
+        assert!(layout("{year}/../..").unwrap_err().contains("without `..`"));
+        assert!(layout("/srv/logs/{year}").unwrap_err().contains("without `..`"));
+        assert!(layout("./{year}").unwrap_err().contains("without `..`"));
+        assert!(
+            layout("{year}/{quarter}")
+                .unwrap_err()
+                .contains("unknown placeholder")
+        );
+        assert!(layout("{year").unwrap_err().contains("unknown placeholder"));
+    }
+
+    #[test]
+    fn dirs_are_rendered_from_the_date() {
+        let base = Path::new("logs");
+        assert_eq!(layout("").unwrap().dir(base, date("2026-10-17")), base);
+        assert_eq!(
+            layout("{year}/{month}/{day}")
+                .unwrap()
+                .dir(base, date("2026-03-02")),
+            Path::new("logs/2026/03/02")
+        );
+        assert_eq!(
+            layout("{year}\\{month}").unwrap().dir(base, date("2026-10-17")),
+            Path::new("logs/2026/10")
+        );
+        assert_eq!(
+            layout("logons-{year}//{week}")
+                .unwrap()
+                .dir(base, date("2026-10-17")),
+            Path::new("logs/logons-2026/W42")
+        );
+    }
+
+    #[test]
+    fn weeks_file_under_their_iso_year() {
+        let weekly = layout("{year}/{week}").unwrap();
+        let base = Path::new("logs");
+        assert_eq!(weekly.dir(base, date("2025-12-29")), Path::new("logs/2026/W01"));
+        assert_eq!(weekly.dir(base, date("2027-01-03")), Path::new("logs/2026/W53"));
+        assert_eq!(weekly.dir(base, date("2027-01-04")), Path::new("logs/2027/W01"));
+        // Without a week the calendar year is the one that makes sense
+        assert_eq!(
+            layout("{year}/{month}").unwrap().dir(base, date("2025-12-29")),
+            Path::new("logs/2025/12")
+        );
+    }
+
+    #[tokio::test]
+    async fn ensure_creates_missing_parents_and_is_idempotent() {
+        let base = tempfile::tempdir().unwrap();
+        let existing = base.path().join("2026");
+        std::fs::create_dir(&existing).unwrap();
+        std::fs::write(existing.join("keep.xlsx"), b"logons").unwrap();
+        let dir = existing.join("10").join("17");
+
+        let provisioner = Provisioner::default();
+        provisioner.ensure(&existing).await.unwrap();
+        provisioner.ensure(&existing).await.unwrap();
+        provisioner.ensure(&dir).await.unwrap();
+        provisioner.ensure(&dir).await.unwrap();
+
+        assert!(dir.is_dir());
+        assert_eq!(std::fs::read(existing.join("keep.xlsx")).unwrap(), b"logons");
+        // The probe cleans up after itself
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn ensure_names_the_directory_it_could_not_create() {
+        let base = tempfile::tempdir().unwrap();
+        let file = base.path().join("2026");
+        std::fs::write(&file, b"not a directory").unwrap();
+        let dir = file.join("10");
+
+        match Provisioner::default().ensure(&dir).await {
+            Err(Error::ProvisionError { path, .. }) => assert_eq!(path, dir),
+            other => panic!("expected a provision error, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! `query`: finds logons in the workbooks, eg: who used LIB-PC-14 on Tuesday period 3.
+
+use chrono::NaiveDate;
+
+use crate::config::Config;
+use crate::prelude::Result;
+use crate::sink::LogFamily;
+use crate::text_sink::{csv_line, jsonl_line};
+use crate::workstation::WorkStationEntry;
+use crate::{ExcelLoggable, history};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The main columns, aligned
+    Table,
+    /// Every column, RFC 4180 with a header row
+    Csv,
+    /// Every column, an array of objects keyed by column name
+    Json,
+}
+
+/// Text filters are case-insensitive and whole-value, `*` matches anything (`LIB-*`). Unset filters
+/// match everything.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub username: Option<String>,
+    pub computer: Option<String>,
+    /// Either OU column, or the full OU path.
+    pub ou:       Option<String>,
+    pub period:   Option<String>,
+    /// Serial number or hardware UUID.
+    pub serial:   Option<String>,
+    pub from:     Option<NaiveDate>,
+    pub to:       Option<NaiveDate>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &WorkStationEntry) -> bool {
+        let date = entry.date_time.date_naive();
+        let text = |pattern: &Option<String>, values: &[&str]| {
+            pattern
+                .as_deref()
+                .is_none_or(|pattern| values.iter().any(|value| wildcard(pattern, value)))
+        };
+
+        self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date <= to)
+            && text(&self.username, &[&entry.username])
+            && text(&self.computer, &[&entry.computer_name])
+            && text(&self.ou, &[&entry.user_ou, &entry.ws_ou, &entry.full_ou])
+            && text(&self.period, &[&entry.period])
+            && text(&self.serial, &[&entry.serial_number, &entry.uuid])
+    }
+}
+
+// Case-insensitive match of the whole `value`, `*` standing for any run of characters.
+fn wildcard(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+
+    // Backtracks to the last `*` on a mismatch, letting it swallow one more character
+    let (mut p, mut v) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == value[v] {
+            p += 1;
+            v += 1;
+        } else if let Some((star_p, star_v)) = star {
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Prints the `family` entries `filter` lets through, oldest first.
+pub async fn query(config: &Config, family: LogFamily, filter: &Filter, format: OutputFormat) -> Result<()> {
+    let entries: Vec<WorkStationEntry> = history::load(config, family)
+        .await?
+        .into_iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+
+    match format {
+        OutputFormat::Table => print_table(&entries),
+        OutputFormat::Csv => {
+            print!("{}", csv_line(WorkStationEntry::COLUMNS));
+            for entry in &entries {
+                print!("{}", csv_line(&entry.field_values()));
+            }
+        }
+        OutputFormat::Json => {
+            let objects: Vec<String> = entries
+                .iter()
+                .map(|entry| {
+                    jsonl_line(WorkStationEntry::COLUMNS, &entry.field_values())
+                        .trim_end()
+                        .to_string()
+                })
+                .collect();
+            println!("[{}]", objects.join(",\n"));
+        }
+    }
+    Ok(())
+}
+
+fn print_table(entries: &[WorkStationEntry]) {
+    if entries.is_empty() {
+        println!("No matching logons");
+        return;
+    }
+
+    let header = [
+        "Logged on",
+        "Username",
+        "Computer",
+        "Period",
+        "User OU",
+        "Computer OU",
+    ];
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.date_time.format("%Y-%m-%d %a %H:%M").to_string(),
+                entry.username.clone(),
+                entry.computer_name.clone(),
+                entry.period.clone(),
+                entry.user_ou.clone(),
+                entry.ws_ou.clone(),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(|column| column.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[&str]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", line(&header));
+    for row in &rows {
+        println!("{}", line(&row.each_ref().map(String::as_str)));
+    }
+    println!("{} logons", rows.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn wildcard_matches_whole_values_ignoring_case() {
+        assert!(wildcard("LIB-*", "lib-pc-14"));
+        assert!(wildcard("LIB-*", "LIB-"));
+        assert!(!wildcard("LIB-*", "XLIB-PC-14"));
+        assert!(!wildcard("LIB-PC", "LIB-PC-14"));
+        assert!(wildcard("*-pc-*", "SCI-PC-02"));
+        assert!(wildcard("jsmith", "JSmith"));
+        assert!(wildcard("ÉCOLE-*", "école-pc-1"));
+        // `*` has to give characters back to the rest of the pattern
+        assert!(wildcard("a*b*c", "aXbYbZc"));
+        assert!(!wildcard("a*b*c", "aXbYbZ"));
+        assert!(wildcard("**", ""));
+        assert!(wildcard("", ""));
+        assert!(!wildcard("", "x"));
+    }
+
+    fn entry(username: &str, day: u32) -> WorkStationEntry {
+        WorkStationEntry::sample(username, Local.with_ymd_and_hms(2026, 3, day, 23, 30, 0).unwrap())
+    }
+
+    fn date(day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2026, 3, day)
+    }
+
+    #[test]
+    fn unset_filter_matches_everything() {
+        assert!(Filter::default().matches(&entry("jsmith", 2)));
+    }
+
+    #[test]
+    fn date_bounds_are_inclusive_local_days() {
+        let filter = Filter {
+            from: date(2),
+            to: date(4),
+            ..Filter::default()
+        };
+        assert!(!filter.matches(&entry("jsmith", 1)));
+        // Late evening is still that day, not the next one in UTC
+        assert!(filter.matches(&entry("jsmith", 2)));
+        assert!(filter.matches(&entry("jsmith", 4)));
+        assert!(!filter.matches(&entry("jsmith", 5)));
+    }
+
+    #[test]
+    fn text_filters_match_any_of_their_columns() {
+        let entry = entry("jsmith", 2);
+        let matches = |filter: Filter| filter.matches(&entry);
+
+        assert!(matches(Filter {
+            computer: Some("lib-*".to_string()),
+            ..Filter::default()
+        }));
+        assert!(!matches(Filter {
+            computer: Some("SCI-*".to_string()),
+            ..Filter::default()
+        }));
+        // Serial number or hardware UUID
+        assert!(matches(Filter {
+            serial: Some("7rb2bg3".to_string()),
+            ..Filter::default()
+        }));
+        assert!(matches(Filter {
+            serial: Some("4c4c4544-*".to_string()),
+            ..Filter::default()
+        }));
+        assert!(!matches(Filter {
+            serial: Some("4C4C4544".to_string()),
+            ..Filter::default()
+        }));
+        // User OU, computer OU or the full path
+        for ou in ["students", "LIB", "OU=Computers_*"] {
+            assert!(matches(Filter {
+                ou: Some(ou.to_string()),
+                ..Filter::default()
+            }));
+        }
+        assert!(matches(Filter {
+            period: Some("period 3".to_string()),
+            ..Filter::default()
+        }));
+        // Every set filter has to match
+        assert!(!matches(Filter {
+            username: Some("jsmith".to_string()),
+            period: Some("Period 4".to_string()),
+            ..Filter::default()
+        }));
+    }
+}
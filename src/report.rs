@@ -0,0 +1,83 @@
+//! `report`: logon counts from the workbooks, grouped by day, period, computer, ...
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+
+use crate::config::Config;
+use crate::history;
+use crate::prelude::Result;
+use crate::sink::LogFamily;
+use crate::workstation::WorkStationEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    Day,
+    Period,
+    Computer,
+    User,
+    UserOu,
+    ComputerOu,
+}
+
+impl GroupBy {
+    fn key(self, entry: &WorkStationEntry) -> String {
+        match self {
+            GroupBy::Day => entry.date_time.format("%Y-%m-%d %a").to_string(),
+            GroupBy::Period => entry.period.clone(),
+            GroupBy::Computer => entry.computer_name.clone(),
+            GroupBy::User => entry.username.clone(),
+            GroupBy::UserOu => entry.user_ou.clone(),
+            GroupBy::ComputerOu => entry.ws_ou.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Group<'a> {
+    logons:    usize,
+    users:     BTreeSet<&'a str>,
+    computers: BTreeSet<&'a str>,
+}
+
+/// Prints one row per group: logons, distinct users and distinct computers, `from`/`to` inclusive.
+pub async fn report(
+    config: &Config,
+    family: LogFamily,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    by: GroupBy,
+) -> Result<()> {
+    let entries = history::load(config, family).await?;
+
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    for entry in &entries {
+        let date = entry.date_time.date_naive();
+        if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) {
+            continue;
+        }
+        let group = groups.entry(by.key(entry)).or_default();
+        group.logons += 1;
+        group.users.insert(&entry.username);
+        group.computers.insert(&entry.computer_name);
+    }
+
+    if groups.is_empty() {
+        println!("No logons found");
+        return Ok(());
+    }
+
+    let width = groups.keys().map(String::len).max().unwrap_or(0).max(5);
+    println!("{:<width$}  {:>7}  {:>7}  {:>9}", "", "Logons", "Users", "Computers");
+    for (key, group) in &groups {
+        println!(
+            "{key:<width$}  {:>7}  {:>7}  {:>9}",
+            group.logons,
+            group.users.len(),
+            group.computers.len()
+        );
+    }
+    let total: usize = groups.values().map(|group| group.logons).sum();
+    println!("{:<width$}  {total:>7}", "Total");
+    Ok(())
+}
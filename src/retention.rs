@@ -0,0 +1,486 @@
+//! Retention: log files nobody has written to for a while are zipped into monthly archives, and
+//! archives past their keep-by date are deleted. Only ever runs on request (`logon_logger retention`),
+//! never as part of a logon.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::Deserialize;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::config::Config;
+use crate::journal;
+use crate::lock::FileLock;
+use crate::prelude::{Error, Result};
+use crate::rotation::{ARCHIVE_DIR, LogFiles};
+use crate::sink::{LogFamily, SinkConfig};
+
+/// `[retention]` config table, one policy per log family.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub workstation: RetentionPolicy,
+    pub user:        RetentionPolicy,
+}
+
+/// Both unset (the default) keeps everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Zip files last written more than this many days ago.
+    pub archive_after_days:  Option<u32>,
+    /// Delete archives of months more than this many months back.
+    pub delete_after_months: Option<u32>,
+}
+
+impl RetentionConfig {
+    pub fn policy(&self, family: LogFamily) -> &RetentionPolicy {
+        match family {
+            LogFamily::Workstation => &self.workstation,
+            LogFamily::User => &self.user,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for family in [LogFamily::Workstation, LogFamily::User] {
+            if self.policy(family).archive_after_days == Some(0) {
+                return Err(Error::ConfigError(format!(
+                    "`retention.{}.archive_after_days` must be at least 1, the current file is still being written",
+                    family.as_str()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a retention run does (or, dry, would do).
+#[derive(Debug, Default)]
+struct Plan {
+    /// Archive -> the files going into it, with their name inside the archive.
+    archive: BTreeMap<PathBuf, Vec<(PathBuf, String)>>,
+    delete:  BTreeSet<PathBuf>,
+}
+
+/// Applies every family's policy to the files of the configured file sinks. `dry_run` only lists
+/// what would be archived and deleted.
+pub async fn run(config: &Config, dry_run: bool) -> Result<()> {
+    let mut file_sets = vec![];
+    for sink in SinkConfig::configured(config) {
+        if let Some(files) = sink.files(config)? {
+            file_sets.push(files);
+        }
+    }
+
+    let retention = config.retention.clone();
+    let now = Local::now();
+    let plan = tokio::task::spawn_blocking(move || plan(&retention, &file_sets, &now)).await??;
+
+    if plan.archive.is_empty() && plan.delete.is_empty() {
+        println!("Nothing to archive or delete");
+        return Ok(());
+    }
+
+    let verb = if dry_run { "would " } else { "" };
+    for (archive, files) in plan.archive {
+        if dry_run {
+            for (source, _) in &files {
+                println!("{verb}archive {} -> {}", source.display(), archive.display());
+            }
+            continue;
+        }
+
+        if let Some(dir) = archive.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let _lock = FileLock::acquire(&archive, &config.lock).await?;
+
+        // Each source stays locked from here until it's deleted, so no logon (or spool replay of an
+        // old entry) can write to it after it was zipped. One written to since it was planned waits
+        // for the next run.
+        let mut locked = vec![];
+        let mut locks = vec![];
+        for (source, name) in files {
+            let lock = FileLock::acquire(&source, &config.lock).await?;
+            if is_busy(&source) || !tokio::fs::try_exists(&source).await? {
+                continue;
+            }
+            locked.push((source, name));
+            locks.push(lock);
+        }
+        if locked.is_empty() {
+            continue;
+        }
+
+        let sources: Vec<PathBuf> = locked.iter().map(|(source, _)| source.clone()).collect();
+        let target = archive.clone();
+        tokio::task::spawn_blocking(move || write_archive(&target, &locked)).await??;
+        remove_archived(&archive, &sources, locks).await;
+        println!("archived {} files into {}", sources.len(), archive.display());
+    }
+
+    for archive in plan.delete {
+        if !dry_run {
+            let _lock = FileLock::acquire(&archive, &config.lock).await?;
+            tokio::fs::remove_file(&archive).await?;
+        }
+        println!("{verb}delete {}", archive.display());
+    }
+
+    Ok(())
+}
+
+fn plan(retention: &RetentionConfig, file_sets: &[LogFiles], now: &DateTime<Local>) -> Result<Plan> {
+    let mut plan = Plan::default();
+    for files in file_sets {
+        let policy = retention.policy(files.family());
+        let archive_dir = files.base_path().join(ARCHIVE_DIR);
+
+        if let Some(days) = policy.archive_after_days {
+            let cutoff = *now - chrono::Duration::days(days.into());
+            let found = files.walk()?.into_iter().filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| files.is_ours(name))
+            });
+
+            for source in found {
+                let modified: DateTime<Local> = std::fs::metadata(&source)?.modified()?.into();
+                if modified >= cutoff || is_busy(&source) {
+                    continue;
+                }
+                // By the period the file covers, a late compaction doesn't move January into March.
+                // A key the rotation can't read back (the config changed since) goes by last write.
+                let month = source
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| files.period_of(name))
+                    .unwrap_or(modified.date_naive())
+                    .format("%Y-%m");
+                let archive = archive_dir.join(format!("{}_{month}.zip", files.family().as_str()));
+                let name = archive_name(files.base_path(), &source);
+                let queued = plan.archive.entry(archive).or_default();
+                // Two sinks of one family can share a base path (and so find the same files)
+                if !queued.iter().any(|(queued, _)| *queued == source) {
+                    queued.push((source, name));
+                }
+            }
+        }
+
+        if let Some(months) = policy.delete_after_months {
+            let current = months_since_epoch(now.date_naive());
+            for archive in list_archives(&archive_dir, files.family())? {
+                if current - archive.1 > i64::from(months) {
+                    plan.delete.insert(archive.0);
+                }
+            }
+        }
+    }
+    Ok(plan)
+}
+
+// A workbook with a journal next to it still has entries to take, however old it looks.
+fn is_busy(path: &Path) -> bool {
+    [journal::journal_path(path), journal::compacting_path(path)]
+        .iter()
+        .any(|sibling| sibling.exists())
+}
+
+// Path under the base path, `/` separated, so the archive keeps the layout's subdirectories.
+fn archive_name(base_path: &Path, source: &Path) -> String {
+    source
+        .strip_prefix(base_path)
+        .unwrap_or(source)
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn months_since_epoch(date: NaiveDate) -> i64 {
+    i64::from(date.year()) * 12 + i64::from(date.month0())
+}
+
+// `<family>_<YYYY-MM>.zip` archives in `dir`, with their month.
+fn list_archives(dir: &Path, family: LogFamily) -> Result<Vec<(PathBuf, i64)>> {
+    let listing = match std::fs::read_dir(dir) {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let prefix = format!("{}_", family.as_str());
+    let mut archives = vec![];
+    for item in listing {
+        let item = item?;
+        let name = item.file_name();
+        let Some(month) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|rest| rest.strip_suffix(".zip"))
+        else {
+            continue;
+        };
+        if let Ok(date) = NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d") {
+            archives.push((item.path(), months_since_epoch(date)));
+        }
+    }
+    Ok(archives)
+}
+
+// Adds `files` to `archive` (created if need be). Written to a copy that's renamed over the
+// original, an interrupted run leaves the old archive and every source file as they were.
+fn write_archive(archive: &Path, files: &[(PathBuf, String)]) -> Result<()> {
+    let mut tmp = archive.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    // Name -> size of what's already in there, a rerun after a crash finds its files already added
+    let mut existing = BTreeMap::new();
+    let mut writer = if archive.exists() {
+        let mut reader = ZipArchive::new(File::open(archive)?)?;
+        for i in 0..reader.len() {
+            let file = reader.by_index(i)?;
+            existing.insert(file.name().to_string(), file.size());
+        }
+        std::fs::copy(archive, &tmp)?;
+        ZipWriter::new_append(File::options().read(true).write(true).open(&tmp)?)?
+    } else {
+        ZipWriter::new(
+            File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp)?,
+        )
+    };
+
+    for (source, name) in files {
+        let metadata = std::fs::metadata(source)?;
+        let name = match existing.get(name) {
+            Some(size) if *size == metadata.len() => continue,
+            // Rewritten since it was archived, keep both
+            Some(_) => unused_name(name, &existing),
+            None => name.clone(),
+        };
+
+        let modified: DateTime<Local> = metadata.modified()?.into();
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(metadata.len() >= u64::from(u32::MAX));
+        if let Ok(modified) = zip::DateTime::try_from(modified.naive_local()) {
+            options = options.last_modified_time(modified);
+        }
+        writer.start_file(name.as_str(), options)?;
+        std::io::copy(&mut File::open(source)?, &mut writer)?;
+        existing.insert(name, metadata.len());
+    }
+
+    let mut file = writer.finish()?;
+    file.flush()?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, archive)?;
+    Ok(())
+}
+
+// `a/b.xlsx` -> `a/b.1.xlsx`, `a/b.2.xlsx`, ... whichever is free.
+fn unused_name(name: &str, taken: &BTreeMap<String, u64>) -> String {
+    let file_start = name.rfind('/').map_or(0, |i| i + 1);
+    let (stem, extension) = match name[file_start..].rfind('.') {
+        Some(dot) => name.split_at(file_start + dot),
+        None => (name, ""),
+    };
+    (1..)
+        .map(|n| format!("{stem}.{n}{extension}"))
+        .find(|candidate| !taken.contains_key(candidate))
+        .expect("some suffix is free")
+}
+
+// The archived files (and workbook backups of them) go, then their `locks`, then any layout
+// directory left empty.
+async fn remove_archived(archive: &Path, sources: &[PathBuf], locks: Vec<FileLock>) {
+    // `<base path>/archive/<name>.zip`
+    let Some(base_path) = archive.parent().and_then(Path::parent) else {
+        return;
+    };
+
+    let mut dirs = BTreeSet::new();
+    for source in sources {
+        let mut backup = source.as_os_str().to_owned();
+        backup.push(".bak");
+        for path in [source.clone(), PathBuf::from(backup)] {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("Couldn't remove archived {}: {}", path.display(), e),
+            }
+        }
+        dirs.extend(
+            source
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != base_path && dir.starts_with(base_path))
+                .map(Path::to_path_buf),
+        );
+    }
+
+    drop(locks);
+
+    // Deepest first, removing a directory that isn't empty just fails
+    for dir in dirs.iter().rev() {
+        let _ = tokio::fs::remove_dir(dir).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const POLICY: &str = "[retention.workstation]\narchive_after_days = 10\ndelete_after_months = 2\n";
+
+    fn setup(dir: &Path) -> (Config, LogFiles) {
+        let toml = format!(
+            "[[sinks]]\ntype = \"xlsx\"\nfamily = \"workstation\"\npath = '{}'\nlayout = \"{{year}}/{{month}}\"\n\n{POLICY}",
+            dir.display()
+        );
+        let config = Config::for_test(dir, &toml).unwrap();
+        let files = SinkConfig::configured(&config)[0]
+            .files(&config)
+            .unwrap()
+            .unwrap();
+        (config, files)
+    }
+
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    // `files`' file for `period`, last written at `modified`.
+    fn written(files: &LogFiles, period: DateTime<Local>, modified: DateTime<Local>) -> PathBuf {
+        let path = files.path(&period);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"workbook").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::from(modified))
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn plan_archives_files_past_the_cutoff_by_the_period_they_cover() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, files) = setup(dir.path());
+        let now = at(2026, 3, 20);
+
+        // Compacted late, in March, but it's January's
+        let late = written(&files, at(2026, 1, 30), at(2026, 3, 5));
+        let recent = written(&files, at(2026, 3, 15), at(2026, 3, 15));
+        let journaled = written(&files, at(2026, 1, 10), at(2026, 1, 10));
+        std::fs::write(journal::journal_path(&journaled), b"").unwrap();
+        let foreign = dir.path().join("2026").join("01").join("notes.txt");
+        std::fs::write(&foreign, b"").unwrap();
+
+        let plan = plan(&config.retention, &[files], &now).unwrap();
+
+        let january = dir.path().join(ARCHIVE_DIR).join("workstation_2026-01.zip");
+        assert_eq!(plan.archive.keys().collect::<Vec<_>>(), [&january]);
+        assert_eq!(plan.archive[&january], [(late, "2026/01/workstation_log_2026-01-30.xlsx".to_string())]);
+        assert!(
+            !plan
+                .archive
+                .values()
+                .flatten()
+                .any(|(source, _)| *source == recent)
+        );
+        assert!(plan.delete.is_empty());
+    }
+
+    #[test]
+    fn plan_deletes_archives_older_than_delete_after_months() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, files) = setup(dir.path());
+        let archive_dir = dir.path().join(ARCHIVE_DIR);
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        for name in [
+            "workstation_2025-12.zip",
+            "workstation_2026-01.zip",
+            "workstation_2026-02.zip",
+            "user_2025-01.zip",
+            "workstation_notes.zip",
+        ] {
+            std::fs::write(archive_dir.join(name), b"").unwrap();
+        }
+
+        let plan = plan(&config.retention, &[files], &at(2026, 3, 20)).unwrap();
+
+        assert_eq!(
+            plan.delete.into_iter().collect::<Vec<_>>(),
+            [archive_dir.join("workstation_2025-12.zip")]
+        );
+    }
+
+    #[test]
+    fn rerun_after_a_crash_adds_nothing_twice_but_keeps_a_rewritten_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("workstation_2026-01.zip");
+        let source = dir.path().join("a.xlsx");
+        std::fs::write(&source, b"first").unwrap();
+        let files = [(source.clone(), "2026/01/a.xlsx".to_string())];
+
+        write_archive(&archive, &files).unwrap();
+        // Crashed before removing the source, the rerun finds it in the archive already
+        write_archive(&archive, &files).unwrap();
+        std::fs::write(&source, b"rewritten since").unwrap();
+        write_archive(&archive, &files).unwrap();
+
+        let reader = ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        let mut names: Vec<&str> = reader.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["2026/01/a.1.xlsx", "2026/01/a.xlsx"]);
+    }
+
+    #[test]
+    fn unused_name_numbers_the_file_not_its_directories() {
+        let taken: BTreeMap<String, u64> = ["a/b.xlsx", "a/b.1.xlsx", "v1.2/notes"]
+            .into_iter()
+            .map(|name| (name.to_string(), 0))
+            .collect();
+
+        assert_eq!(unused_name("a/b.xlsx", &taken), "a/b.2.xlsx");
+        assert_eq!(unused_name("c.csv", &taken), "c.1.csv");
+        assert_eq!(unused_name("v1.2/notes", &taken), "v1.2/notes.1");
+    }
+
+    #[tokio::test]
+    async fn run_archives_and_removes_the_file_and_its_emptied_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, files) = setup(dir.path());
+        let then = Local::now() - chrono::Duration::days(60);
+        let source = written(&files, then, then);
+
+        run(&config, false).await.unwrap();
+
+        let archive = dir
+            .path()
+            .join(ARCHIVE_DIR)
+            .join(format!("workstation_{}.zip", then.format("%Y-%m")));
+        assert!(archive.exists());
+        assert!(!source.exists());
+        // Its lock went before its directory did, and the archive's after the run
+        assert!(!source.parent().unwrap().exists());
+        assert!(!PathBuf::from(format!("{}.lock", archive.display())).exists());
+    }
+}
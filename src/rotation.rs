@@ -0,0 +1,530 @@
+//! Which file an entry goes to: the rotation policy turns the logon time into a key (`2026-10-17`,
+//! `2026-W42`, ...) and the file name template puts the key (and family) into a name.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, Weekday};
+use serde::Deserialize;
+
+use crate::calendar::DateRange;
+use crate::config::Config;
+use crate::prelude::{Error, Result};
+use crate::provision::DirLayout;
+use crate::sink::LogFamily;
+
+/// Old files are zipped into `<base path>/archive/`, see `retention`.
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// How much time one log file covers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileRotation {
+    /// `2026-10-17`
+    #[default]
+    Daily,
+    /// ISO week, `2026-W42`. The year is the ISO week's, so 2025-12-31 is `2026-W01`.
+    Weekly,
+    /// `2026-10`
+    Monthly,
+    /// `2026-term-4`, from the configured `[calendar]` terms. A holiday belongs to the term before
+    /// it, days before the year's first term to `<year>-pre-term`.
+    Term,
+}
+
+/// File name without the extension, `{key}` is required, eg: `{family}_log_{key}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FileTemplate(String);
+
+impl Default for FileTemplate {
+    fn default() -> Self {
+        Self("{family}_log_{key}".to_string())
+    }
+}
+
+impl TryFrom<String> for FileTemplate {
+    type Error = String;
+
+    fn try_from(template: String) -> std::result::Result<Self, Self::Error> {
+        if template.matches("{key}").count() != 1 {
+            return Err(format!("file name \"{template}\" must contain `{{key}}` exactly once"));
+        }
+        if template.contains(['/', '\\']) {
+            return Err(format!("file name \"{template}\" can't contain a path separator, see `layout`"));
+        }
+        let rest = template.replace("{key}", "").replace("{family}", "");
+        if rest.contains(['{', '}']) {
+            return Err(format!(
+                "file name \"{template}\" has an unknown placeholder, expected `{{family}}` or `{{key}}`"
+            ));
+        }
+        Ok(Self(template))
+    }
+}
+
+/// Everything that decides where a sink's files go.
+#[derive(Debug, Clone)]
+pub struct LogFiles {
+    base_path: PathBuf,
+    family:    LogFamily,
+    layout:    DirLayout,
+    rotation:  FileRotation,
+    file_name: FileTemplate,
+    extension: &'static str,
+    /// Sorted by start, only needed for `FileRotation::Term`.
+    terms:     Arc<[DateRange]>,
+}
+
+impl LogFiles {
+    pub fn new(
+        config: &Config,
+        base_path: PathBuf,
+        family: LogFamily,
+        layout: DirLayout,
+        rotation: FileRotation,
+        file_name: FileTemplate,
+        extension: &'static str,
+    ) -> Result<Self> {
+        let terms: Arc<[DateRange]> = config.schedule.calendar().terms().into();
+        if rotation == FileRotation::Term && terms.is_empty() {
+            return Err(Error::ConfigError(
+                "`rotation = \"term\"` needs `[[calendar.terms]]` (or an `.ics` import with terms)"
+                    .to_string(),
+            ));
+        }
+        Ok(Self {
+            base_path,
+            family,
+            layout,
+            rotation,
+            file_name,
+            extension,
+            terms,
+        })
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    pub fn family(&self) -> LogFamily {
+        self.family
+    }
+
+    /// The rotation key `date_time` falls under.
+    pub fn key(&self, date_time: &DateTime<Local>) -> String {
+        let date = date_time.date_naive();
+        match self.rotation {
+            FileRotation::Daily => date.format("%Y-%m-%d").to_string(),
+            FileRotation::Weekly => {
+                let week = date.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            FileRotation::Monthly => date.format("%Y-%m").to_string(),
+            FileRotation::Term => {
+                match self.term(date) {
+                    Some(term) => {
+                        let name = slug(&term.name);
+                        if name.is_empty() {
+                            term.start.format("%Y-term-%m-%d").to_string()
+                        } else {
+                            format!("{}-{}", term.start.year(), name)
+                        }
+                    }
+                    // Before this year's first term (or the terms configured at all)
+                    None => format!("{}-pre-term", date.year()),
+                }
+            }
+        }
+    }
+
+    /// First day of the rotation period `date_time` falls in, the same for every entry of a file.
+    pub fn period_start(&self, date_time: &DateTime<Local>) -> NaiveDate {
+        self.start_of(date_time.date_naive())
+    }
+
+    fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self.rotation {
+            FileRotation::Daily => date,
+            FileRotation::Weekly => date - Days::new(date.weekday().num_days_from_monday().into()),
+            FileRotation::Monthly => date.with_day(1).expect("every month has a 1st"),
+            FileRotation::Term => {
+                match self.term(date) {
+                    Some(term) => term.start,
+                    None => {
+                        NaiveDate::from_ymd_opt(date.year(), 1, 1).expect("every year has a 1st of January")
+                    }
+                }
+            }
+        }
+    }
+
+    // The term `date` counts towards: the last one started, holidays after it included, unless it
+    // started in an earlier year and is over.
+    fn term(&self, date: NaiveDate) -> Option<&DateRange> {
+        self.terms
+            .iter()
+            .rev()
+            .find(|term| term.start <= date)
+            .filter(|term| term.start.year() == date.year() || term.contains(date))
+    }
+
+    /// Directory for `date_time`'s file. Rendered from the start of its rotation period, so a week or
+    /// term crossing into a new month still has all of its file in one directory.
+    pub fn dir(&self, date_time: &DateTime<Local>) -> PathBuf {
+        self.layout.dir(&self.base_path, self.period_start(date_time))
+    }
+
+    /// Directories of `date_time`'s rotation period and the one before it (once each, they're often
+    /// the same), where the files a logon can still expect to be written to are.
+    pub fn recent_dirs(&self, date_time: &DateTime<Local>) -> Vec<PathBuf> {
+        let start = self.period_start(date_time);
+        let mut dirs = vec![self.layout.dir(&self.base_path, start)];
+        if let Some(before) = start.pred_opt() {
+            let previous = self.layout.dir(&self.base_path, self.start_of(before));
+            if previous != dirs[0] {
+                dirs.push(previous);
+            }
+        }
+        dirs
+    }
+
+    pub fn file_name(&self, date_time: &DateTime<Local>) -> String {
+        let stem = self
+            .file_name
+            .0
+            .replace("{family}", self.family.as_str())
+            .replace("{key}", &self.key(date_time));
+        format!("{stem}.{}", self.extension)
+    }
+
+    pub fn path(&self, date_time: &DateTime<Local>) -> PathBuf {
+        self.dir(date_time).join(self.file_name(date_time))
+    }
+
+    /// Every file under the base path, layout subdirectories included and archives left out. Not
+    /// all of them need be ours, see `is_ours`.
+    pub fn walk(&self) -> Result<Vec<PathBuf>> {
+        let mut found = vec![];
+        walk_dir(&self.base_path, &self.base_path, &mut found)?;
+        found.sort();
+        Ok(found)
+    }
+
+    /// Whether `file_name` looks like one of ours (any key), eg: for picking out old files.
+    pub fn is_ours(&self, file_name: &str) -> bool {
+        self.key_of(file_name).is_some()
+    }
+
+    /// First day of the rotation period one of our files covers, from the key in its name. `None`
+    /// for a name that isn't ours or a key this rotation wouldn't have made.
+    pub fn period_of(&self, file_name: &str) -> Option<NaiveDate> {
+        let key = self.key_of(file_name)?;
+        match self.rotation {
+            FileRotation::Daily => NaiveDate::parse_from_str(key, "%Y-%m-%d").ok(),
+            FileRotation::Weekly => {
+                let (year, week) = key.split_once("-W")?;
+                NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)
+            }
+            FileRotation::Monthly => NaiveDate::parse_from_str(&format!("{key}-01"), "%Y-%m-%d").ok(),
+            FileRotation::Term => {
+                let (year, name) = key.split_once('-')?;
+                let year: i32 = year.parse().ok()?;
+                if name == "pre-term" {
+                    return NaiveDate::from_ymd_opt(year, 1, 1);
+                }
+                if let Ok(start) = NaiveDate::parse_from_str(key, "%Y-term-%m-%d") {
+                    return Some(start);
+                }
+                self.terms
+                    .iter()
+                    .find(|term| term.start.year() == year && slug(&term.name) == name)
+                    .map(|term| term.start)
+            }
+        }
+    }
+
+    // The `{key}` part of `file_name`, when it's one of ours.
+    fn key_of<'a>(&self, file_name: &'a str) -> Option<&'a str> {
+        let full =
+            format!("{}.{}", self.file_name.0, self.extension).replace("{family}", self.family.as_str());
+        let (prefix, suffix) = full.split_once("{key}").expect("validated to contain {key}");
+        file_name
+            .strip_prefix(prefix)?
+            .strip_suffix(suffix)
+            .filter(|key| !key.is_empty())
+    }
+}
+
+// Files under `dir`, recursing into everything but the base path's archive directory.
+fn walk_dir(base_path: &Path, dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    let listing = match std::fs::read_dir(dir) {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    for item in listing {
+        let item = item?;
+        let file_type = item.file_type()?;
+        if file_type.is_dir() {
+            if dir == base_path && item.file_name() == ARCHIVE_DIR {
+                continue;
+            }
+            walk_dir(base_path, &item.path(), found)?;
+        } else if file_type.is_file() {
+            found.push(item.path());
+        }
+    }
+    Ok(())
+}
+
+// `Term 1` -> `term-1`, safe in a file name.
+fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const TERMS: &str = r#"
+        [[calendar.terms]]
+        name = "Term 1"
+        start = "2026-01-28"
+        end = "2026-04-02"
+
+        [[calendar.terms]]
+        name = "Term 2"
+        start = "2026-04-20"
+        end = "2026-06-26"
+
+        [[calendar.terms]]
+        name = "Term 4"
+        start = "2026-10-05"
+        end = "2026-12-17"
+
+        [[calendar.terms]]
+        name = "Term 1"
+        start = "2027-01-27"
+        end = "2027-04-01"
+    "#;
+
+    fn files_with(rotation: FileRotation, calendar: &str, file_name: &str) -> LogFiles {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_test(dir.path(), calendar).unwrap();
+        LogFiles::new(
+            &config,
+            PathBuf::from("logs"),
+            LogFamily::Workstation,
+            DirLayout::try_from("{year}/{month}".to_string()).unwrap(),
+            rotation,
+            FileTemplate::try_from(file_name.to_string()).unwrap(),
+            "xlsx",
+        )
+        .unwrap()
+    }
+
+    fn files(rotation: FileRotation) -> LogFiles {
+        files_with(rotation, TERMS, "{family}_log_{key}")
+    }
+
+    fn at(date: &str) -> DateTime<Local> {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        Local
+            .with_ymd_and_hms(date.year(), date.month(), date.day(), 12, 0, 0)
+            .unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    // (logon day, key, first day of its period) for each case
+    fn assert_periods(files: &LogFiles, cases: &[(&str, &str, &str)]) {
+        for (day, key, start) in cases {
+            assert_eq!(files.key(&at(day)), *key, "key of {day}");
+            assert_eq!(files.period_start(&at(day)), date(start), "period start of {day}");
+        }
+    }
+
+    #[test]
+    fn daily_and_monthly_keys() {
+        assert_periods(
+            &files(FileRotation::Daily),
+            &[
+                ("2026-10-31", "2026-10-31", "2026-10-31"),
+                ("2026-11-01", "2026-11-01", "2026-11-01"),
+                ("2026-12-31", "2026-12-31", "2026-12-31"),
+            ],
+        );
+        assert_periods(
+            &files(FileRotation::Monthly),
+            &[
+                ("2026-10-31", "2026-10", "2026-10-01"),
+                ("2026-11-01", "2026-11", "2026-11-01"),
+                ("2027-01-01", "2027-01", "2027-01-01"),
+            ],
+        );
+    }
+
+    #[test]
+    fn weekly_keys_use_the_iso_week_year() {
+        assert_periods(
+            &files(FileRotation::Weekly),
+            &[
+                ("2026-10-17", "2026-W42", "2026-10-12"),
+                // Monday 2025-12-29 starts 2026's first week
+                ("2025-12-28", "2025-W52", "2025-12-22"),
+                ("2025-12-31", "2026-W01", "2025-12-29"),
+                ("2026-01-04", "2026-W01", "2025-12-29"),
+                // 2026 has 53 weeks, its last runs into 2027
+                ("2026-12-31", "2026-W53", "2026-12-28"),
+                ("2027-01-03", "2026-W53", "2026-12-28"),
+                ("2027-01-04", "2027-W01", "2027-01-04"),
+            ],
+        );
+    }
+
+    #[test]
+    fn term_keys_cover_holidays_and_the_start_of_the_year() {
+        assert_periods(
+            &files(FileRotation::Term),
+            &[
+                ("2026-01-10", "2026-pre-term", "2026-01-01"),
+                ("2026-01-28", "2026-term-1", "2026-01-28"),
+                // Holidays count towards the term before them
+                ("2026-04-10", "2026-term-1", "2026-01-28"),
+                ("2026-09-30", "2026-term-2", "2026-04-20"),
+                ("2026-12-31", "2026-term-4", "2026-10-05"),
+                // Not the previous year's term, that one's over
+                ("2027-01-10", "2027-pre-term", "2027-01-01"),
+                ("2027-02-01", "2027-term-1", "2027-01-27"),
+            ],
+        );
+    }
+
+    #[test]
+    fn terms_run_across_new_year_and_unnamed_terms_key_by_date() {
+        let calendar = r#"
+            [[calendar.terms]]
+            name = "Fall"
+            start = "2026-09-02"
+            end = "2027-01-22"
+
+            [[calendar.terms]]
+            name = "---"
+            start = "2027-01-25"
+            end = "2027-06-10"
+        "#;
+        let files = files_with(FileRotation::Term, calendar, "{family}_log_{key}");
+        assert_periods(
+            &files,
+            &[
+                ("2027-01-10", "2026-fall", "2026-09-02"),
+                // Over, and the year has changed since it started
+                ("2027-01-23", "2027-pre-term", "2027-01-01"),
+                ("2027-02-01", "2027-term-01-25", "2027-01-25"),
+                ("2027-08-01", "2027-term-01-25", "2027-01-25"),
+            ],
+        );
+        assert_eq!(files.period_of("workstation_log_2027-term-01-25.xlsx"), Some(date("2027-01-25")));
+    }
+
+    #[test]
+    fn period_of_reads_back_the_period_start() {
+        let rotations = [
+            files(FileRotation::Daily),
+            files(FileRotation::Weekly),
+            files(FileRotation::Monthly),
+            files(FileRotation::Term),
+            files_with(
+                FileRotation::Term,
+                "[[calendar.terms]]\nname = \"!\"\nstart = \"2026-02-02\"",
+                "logons {key}",
+            ),
+        ];
+        for files in &rotations {
+            let mut day = date("2025-12-01");
+            while day < date("2027-03-01") {
+                let logon = at(&day.to_string());
+                let name = files.file_name(&logon);
+                assert_eq!(files.period_of(&name), Some(files.period_start(&logon)), "{name}");
+                day = day.succ_opt().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn only_our_names_with_a_valid_key_have_a_period() {
+        let daily = files(FileRotation::Daily);
+        assert!(daily.is_ours("workstation_log_2026-10-17.xlsx"));
+        assert!(!daily.is_ours("workstation_log_2026-10-17.xlsx.journal"));
+        assert!(!daily.is_ours("user_log_2026-10-17.xlsx"));
+        assert!(!daily.is_ours("workstation_log_.xlsx"));
+        assert!(!daily.is_ours("workstation_log_2026-10-17.csv"));
+        assert_eq!(daily.period_of("workstation_log_2026-13-01.xlsx"), None);
+
+        assert_eq!(files(FileRotation::Weekly).period_of("workstation_log_2026-W54.xlsx"), None);
+        assert_eq!(files(FileRotation::Monthly).period_of("workstation_log_2026-10-01.xlsx"), None);
+        assert_eq!(files(FileRotation::Term).period_of("workstation_log_2026-term-3.xlsx"), None);
+
+        // Key in the middle of the name
+        let custom = files_with(FileRotation::Monthly, TERMS, "{key}-{family}");
+        assert_eq!(custom.file_name(&at("2026-10-17")), "2026-10-workstation.xlsx");
+        assert_eq!(custom.period_of("2026-10-workstation.xlsx"), Some(date("2026-10-01")));
+        assert!(!custom.is_ours("2026-10-user.xlsx"));
+    }
+
+    #[test]
+    fn file_templates_are_checked() {
+        let template = |name: &str| FileTemplate::try_from(name.to_string());
+        assert_eq!(template("{family}_log_{key}"), Ok(FileTemplate::default()));
+        assert!(template("{key}").is_ok());
+
+        assert!(template("{family}_log").unwrap_err().contains("exactly once"));
+        assert!(template("{key}_{key}").unwrap_err().contains("exactly once"));
+        assert!(template("logs/{key}").unwrap_err().contains("path separator"));
+        assert!(template("logs\\{key}").unwrap_err().contains("path separator"));
+        assert!(
+            template("{date}_{key}")
+                .unwrap_err()
+                .contains("unknown placeholder")
+        );
+    }
+
+    #[test]
+    fn term_rotation_needs_terms() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_test(dir.path(), "").unwrap();
+        let made = LogFiles::new(
+            &config,
+            PathBuf::from("logs"),
+            LogFamily::User,
+            DirLayout::default(),
+            FileRotation::Term,
+            FileTemplate::default(),
+            "csv",
+        );
+        assert!(matches!(made, Err(Error::ConfigError(_))));
+    }
+
+    #[test]
+    fn directory_is_the_period_starts() {
+        let weekly = files(FileRotation::Weekly);
+        // Week starting Monday 2026-09-28, all of it in September's directory
+        assert_eq!(weekly.dir(&at("2026-10-02")), Path::new("logs/2026/09"));
+        assert_eq!(weekly.recent_dirs(&at("2026-10-02")), [PathBuf::from("logs/2026/09")]);
+        assert_eq!(
+            weekly.recent_dirs(&at("2026-10-05")),
+            [PathBuf::from("logs/2026/10"), PathBuf::from("logs/2026/09")]
+        );
+    }
+}
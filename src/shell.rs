@@ -0,0 +1,536 @@
+//! Shells the `PsExecutor` can drive: Windows PowerShell, PowerShell Core, POSIX `sh` and a canned mock.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+
+use crate::executor::PsCommand;
+use crate::prelude::{Error, Result};
+
+/// Renders a `PsCommand` into this shell's syntax and runs it.
+#[async_trait]
+pub trait ShellBackend: Send + Sync + 'static {
+    /// Quotes `value` so the shell sees it as a single literal.
+    fn quote(&self, value: &str) -> String;
+
+    /// `Name -Param 'value' | Select-Object -Property 'A' | ConvertTo-Json -Compress`, override for
+    /// shells that aren't PowerShell.
+    fn render(&self, command: &PsCommand) -> String {
+        let mut script = command.name.to_string();
+        for (param, values) in &command.params {
+            script.push_str(&format!(" -{} {}", param, quote_list(self, values)));
+        }
+        if !command.select.is_empty() {
+            script.push_str(&format!(" | Select-Object -Property {}", quote_list(self, &command.select)));
+        }
+        if command.json {
+            script.push_str(" | ConvertTo-Json -Compress");
+        }
+        script
+    }
+
+    /// Runs `script`, returning trimmed stdout. The child must not outlive `timeout`.
+    async fn run(&self, script: &str, timeout: Duration) -> Result<String>;
+
+    /// Starts a long-lived host to run many scripts in, `None` if this shell only does one process per
+    /// script.
+    async fn open_session(&self) -> Result<Option<Box<dyn ShellSession>>> {
+        Ok(None)
+    }
+}
+
+/// `'a','b'`, PowerShell's list syntax, reused by the `sh` stand-in convention.
+fn quote_list<B: ShellBackend + ?Sized>(backend: &B, values: &[impl AsRef<str>]) -> String {
+    values
+        .iter()
+        .map(|v| backend.quote(v.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A long-lived shell host, scripts run one at a time.
+#[async_trait]
+pub trait ShellSession: Send {
+    /// Same contract as `ShellBackend::run`.
+    async fn run(&mut self, script: &str, timeout: Duration) -> Result<String>;
+
+    /// `false` once the host crashed, timed out or lost sync, the caller should start a new one.
+    fn healthy(&self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShellKind {
+    /// `powershell.exe`, ships with Windows.
+    #[default]
+    WindowsPowershell,
+    /// PowerShell 7+ (`pwsh`), Windows, Linux or macOS.
+    Pwsh,
+    /// `sh -c`, for stand-in scripts on Linux.
+    Sh,
+    /// Canned responses from the `mock` file, nothing is spawned.
+    Mock,
+}
+
+impl ShellKind {
+    /// `mock` is the canned response file, only read for `ShellKind::Mock`.
+    pub fn backend(self, mock: Option<&Path>) -> Result<Arc<dyn ShellBackend>> {
+        Ok(match self {
+            ShellKind::WindowsPowershell => Arc::new(PowerShell::windows()),
+            ShellKind::Pwsh => Arc::new(PowerShell::core()),
+            ShellKind::Sh => Arc::new(PosixSh),
+            ShellKind::Mock => {
+                let path = mock.ok_or_else(|| {
+                    Error::ConfigError("`executor.shell = \"mock\"` needs `executor.mock`".to_string())
+                })?;
+                Arc::new(MockShell::from_path(path)?)
+            }
+        })
+    }
+}
+
+/// Windows PowerShell or PowerShell Core, the script goes over `-EncodedCommand`.
+#[derive(Debug, Clone)]
+pub struct PowerShell {
+    program: &'static str,
+}
+
+impl PowerShell {
+    pub fn windows() -> Self {
+        Self {
+            program: "powershell.exe",
+        }
+    }
+
+    pub fn core() -> Self {
+        Self { program: "pwsh" }
+    }
+}
+
+#[async_trait]
+impl ShellBackend for PowerShell {
+    /// Nothing expands inside single quotes (`$()`, backticks, newlines are all literal), the only
+    /// special characters are the quotes themselves, which PowerShell also accepts in their
+    /// typographic forms (U+2018..U+201B), each is escaped by doubling it.
+    fn quote(&self, value: &str) -> String {
+        let mut out = String::with_capacity(value.len() + 2);
+        out.push('\'');
+        for c in value.chars() {
+            if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                out.push(c);
+            }
+            out.push(c);
+        }
+        out.push('\'');
+        out
+    }
+
+    async fn run(&self, script: &str, timeout: Duration) -> Result<String> {
+        run_process(
+            self.program,
+            &[
+                "-NoProfile",
+                "-NonInteractive",
+                "-EncodedCommand",
+                &encode_ps(script),
+            ],
+            timeout,
+        )
+        .await
+    }
+
+    async fn open_session(&self) -> Result<Option<Box<dyn ShellSession>>> {
+        let mut child = tokio::process::Command::new(self.program)
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-EncodedCommand",
+                &encode_ps(POWERSHELL_HOST),
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Script errors come back in the response, anything else here is noise we'd have to drain
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(Error::CommandSpawnError)?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(Error::Generic("PowerShell host has no stdio pipes".to_string()));
+        };
+
+        Ok(Some(Box::new(PowerShellSession {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            healthy: true,
+        })))
+    }
+}
+
+// Marks the response line, anything else the script prints on stdout is skipped.
+const RESPONSE_SENTINEL: &str = "<<LOGON_LOGGER>>";
+
+/// Host loop fed one base64 (UTF-8) script per stdin line, answering with one sentinel + JSON line.
+const POWERSHELL_HOST: &str = r#"
+$ErrorActionPreference = 'Stop'
+[Console]::OutputEncoding = [Text.Encoding]::UTF8
+while ($null -ne ($line = [Console]::In.ReadLine())) {
+    try {
+        $script = [Text.Encoding]::UTF8.GetString([Convert]::FromBase64String($line))
+        $out = (& ([ScriptBlock]::Create($script)) | Out-String).Trim()
+        $resp = @{ ok = $true; stdout = $out; stderr = '' }
+    } catch {
+        $resp = @{ ok = $false; stdout = ''; stderr = ($_ | Out-String).Trim() }
+    }
+    [Console]::Out.WriteLine('<<LOGON_LOGGER>>' + ($resp | ConvertTo-Json -Compress))
+    [Console]::Out.Flush()
+}
+"#;
+
+#[derive(Debug, Deserialize)]
+struct HostResponse {
+    ok:     bool,
+    stdout: String,
+    stderr: String,
+}
+
+pub struct PowerShellSession {
+    child:   Child,
+    stdin:   ChildStdin,
+    stdout:  BufReader<ChildStdout>,
+    healthy: bool,
+}
+
+impl PowerShellSession {
+    async fn exchange(&mut self, script: &str) -> std::io::Result<Option<HostResponse>> {
+        let request = format!("{}\n", BASE64.encode(script.as_bytes()));
+        self.stdin.write_all(request.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if self.stdout.read_until(b'\n', &mut line).await? == 0 {
+                return Ok(None);
+            }
+            let text = String::from_utf8_lossy(&line);
+            if let Some(json) = text.trim().strip_prefix(RESPONSE_SENTINEL) {
+                return serde_json::from_str(json)
+                    .map(Some)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ShellSession for PowerShellSession {
+    async fn run(&mut self, script: &str, timeout: Duration) -> Result<String> {
+        let exchanged = tokio::time::timeout(timeout, self.exchange(script)).await;
+
+        match exchanged {
+            Err(_) => {
+                self.healthy = false;
+                Err(Error::CommandTimeoutError(timeout))
+            }
+            Ok(Err(e)) => {
+                self.healthy = false;
+                Err(Error::ShellCrashError(format!("PowerShell host I/O failed: {e}")))
+            }
+            Ok(Ok(None)) => {
+                self.healthy = false;
+                // stdout closed, so it's exiting, give it a moment to report how
+                let status = tokio::time::timeout(Duration::from_secs(1), self.child.wait()).await;
+                let code = status.ok().and_then(|s| s.ok()).and_then(|s| s.code());
+                Err(Error::ShellCrashError(format!(
+                    "PowerShell host exited with status {}",
+                    code.map_or("unknown".to_string(), |c| c.to_string())
+                )))
+            }
+            Ok(Ok(Some(response))) if response.ok => Ok(response.stdout),
+            Ok(Ok(Some(response))) => {
+                Err(Error::CommandExitError {
+                    status: Some(1),
+                    stderr: response.stderr,
+                })
+            }
+        }
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+// Base64 of the UTF-16LE script, what `-EncodedCommand` expects.
+fn encode_ps(script: &str) -> String {
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    BASE64.encode(utf16)
+}
+
+/// `sh -c`. Renders `Name -Param 'value' -Select 'A','B' -AsJson`, so an executable named after the
+/// cmdlet on `PATH` can stand in for it.
+#[derive(Debug, Clone)]
+pub struct PosixSh;
+
+#[async_trait]
+impl ShellBackend for PosixSh {
+    /// `'...'` with every `'` closed, escaped and reopened (`'\''`).
+    fn quote(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+
+    fn render(&self, command: &PsCommand) -> String {
+        let mut script = self.quote(command.name);
+        for (param, values) in &command.params {
+            script.push_str(&format!(" -{} {}", param, quote_list(self, values)));
+        }
+        if !command.select.is_empty() {
+            script.push_str(&format!(" -Select {}", quote_list(self, &command.select)));
+        }
+        if command.json {
+            script.push_str(" -AsJson");
+        }
+        script
+    }
+
+    async fn run(&self, script: &str, timeout: Duration) -> Result<String> {
+        run_process("sh", &["-c", script], timeout).await
+    }
+}
+
+/// One canned reply, matched against the rendered (PowerShell syntax) script.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockResponse {
+    pub command:  String,
+    #[serde(default)]
+    pub stdout:   String,
+    #[serde(default)]
+    pub stderr:   String,
+    #[serde(default)]
+    pub exit:     i32,
+    /// Simulates a slow directory, counts against the command timeout.
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MockFile {
+    responses: Vec<MockResponse>,
+}
+
+/// Maps scripts to canned stdout/stderr/exit codes. Unknown scripts exit 1.
+#[derive(Debug, Clone, Default)]
+pub struct MockShell {
+    responses: HashMap<String, MockResponse>,
+}
+
+impl MockShell {
+    pub fn new(responses: impl IntoIterator<Item = MockResponse>) -> Self {
+        Self {
+            responses: responses.into_iter().map(|r| (r.command.clone(), r)).collect(),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            Error::ConfigError(format!("Failed to read shell mock {}: {}", path.display(), e))
+        })?;
+        let file: MockFile = toml::from_str(&raw).map_err(|source| {
+            Error::ConfigParseError {
+                path: PathBuf::from(path),
+                source,
+            }
+        })?;
+        Ok(Self::new(file.responses))
+    }
+}
+
+#[async_trait]
+impl ShellBackend for MockShell {
+    fn quote(&self, value: &str) -> String {
+        PowerShell::windows().quote(value)
+    }
+
+    async fn run(&self, script: &str, timeout: Duration) -> Result<String> {
+        let Some(response) = self.responses.get(script) else {
+            return Err(Error::CommandExitError {
+                status: Some(1),
+                stderr: format!("No canned response for: {script}"),
+            });
+        };
+
+        let delay = Duration::from_millis(response.delay_ms);
+        if delay > timeout {
+            tokio::time::sleep(timeout).await;
+            return Err(Error::CommandTimeoutError(timeout));
+        }
+        tokio::time::sleep(delay).await;
+
+        if response.exit == 0 {
+            Ok(response.stdout.trim().to_string())
+        } else {
+            Err(Error::CommandExitError {
+                status: Some(response.exit),
+                stderr: response.stderr.trim().to_string(),
+            })
+        }
+    }
+}
+
+// Spawns `program`, killing it if it's still running after `timeout` (or if the future is dropped).
+async fn run_process(program: &str, args: &[&str], timeout: Duration) -> Result<String> {
+    let child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(Error::CommandSpawnError)?;
+
+    let out = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| Error::CommandTimeoutError(timeout))??;
+
+    if out.status.success() {
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    } else {
+        Err(Error::CommandExitError {
+            status: out.status.code(),
+            stderr: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Payloads that break out of (or expand inside) a naively quoted string.
+    const PAYLOADS: [&str; 6] = [
+        "o'brien",
+        "a`b`$c",
+        "$(Remove-Item C:\\ -Recurse)",
+        "jdoe\nRemove-Item C:\\",
+        "o\u{2019}brien\u{2018}; calc; \u{201B}",
+        "x\u{201A}'; calc #",
+    ];
+
+    // Reads a PowerShell single-quoted literal back the way the parser would, `None` if it ends early.
+    fn unquote_ps(quoted: &str) -> Option<String> {
+        let is_quote = |c: char| matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}');
+        let mut chars = quoted.strip_prefix('\'')?.chars().peekable();
+        let mut out = String::new();
+        while let Some(c) = chars.next() {
+            if is_quote(c) {
+                match chars.peek() {
+                    Some(next) if is_quote(*next) => out.push(chars.next()?),
+                    // The closing quote has to be the very last character
+                    None if c == '\'' => return Some(out),
+                    _ => return None,
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn powershell_quote_keeps_payloads_literal() {
+        let shell = PowerShell::windows();
+        for payload in PAYLOADS {
+            let quoted = shell.quote(payload);
+            assert_eq!(unquote_ps(&quoted).as_deref(), Some(payload), "{quoted}");
+        }
+    }
+
+    #[test]
+    fn powershell_quote_doubles_every_quote_kind() {
+        let shell = PowerShell::windows();
+        assert_eq!(shell.quote("o'brien"), "'o''brien'");
+        assert_eq!(shell.quote("o\u{2019}brien"), "'o\u{2019}\u{2019}brien'");
+        assert_eq!(
+            shell.quote("\u{2018}\u{201A}\u{201B}"),
+            "'\u{2018}\u{2018}\u{201A}\u{201A}\u{201B}\u{201B}'"
+        );
+        // Only quotes are special inside single quotes
+        assert_eq!(shell.quote("a`b $(c)\nd"), "'a`b $(c)\nd'");
+    }
+
+    #[test]
+    fn rendered_command_quotes_every_value() {
+        let command = PsCommand::cmdlet("Get-ADUser")
+            .param("Identity", "o'brien$(calc)")
+            .param_list("Properties", ["mail", "x'y"])
+            .select(&["DistinguishedName"]);
+        assert_eq!(
+            PowerShell::windows().render(&command),
+            "Get-ADUser -Identity 'o''brien$(calc)' -Properties 'mail','x''y' | Select-Object -Property \
+             'DistinguishedName'"
+        );
+    }
+
+    #[test]
+    fn encoded_command_round_trips_as_utf16le() {
+        for payload in PAYLOADS {
+            let script = format!("Get-ADUser -Identity {}", PowerShell::windows().quote(payload));
+            let bytes = BASE64.decode(encode_ps(&script)).unwrap();
+            let units: Vec<u16> = bytes
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|pair| u16::from_le_bytes(*pair))
+                .collect();
+            assert_eq!(String::from_utf16(&units).unwrap(), script);
+            // Nothing but base64 reaches the command line
+            assert!(
+                encode_ps(&script)
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '='))
+            );
+        }
+    }
+
+    #[test]
+    fn sh_quote_keeps_payloads_literal() {
+        let shell = PosixSh;
+        assert_eq!(shell.quote("o'brien"), r"'o'\''brien'");
+        for payload in PAYLOADS {
+            let quoted = shell.quote(payload);
+            // Outside the `'\''` escapes everything is inside single quotes, where nothing expands
+            let inner = quoted
+                .strip_prefix('\'')
+                .and_then(|q| q.strip_suffix('\''))
+                .unwrap();
+            assert_eq!(inner.replace(r"'\''", "'"), payload);
+            assert!(!inner.replace(r"'\''", "").contains('\''));
+        }
+    }
+
+    #[tokio::test]
+    async fn sh_passes_payloads_through_unexpanded() {
+        if cfg!(not(unix)) {
+            return;
+        }
+        let shell = PosixSh;
+        for payload in PAYLOADS {
+            let script = format!("printf '%s' {}", shell.quote(payload));
+            let out = shell.run(&script, Duration::from_secs(5)).await.unwrap();
+            assert_eq!(out, payload.trim());
+        }
+    }
+}
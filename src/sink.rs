@@ -0,0 +1,714 @@
+//! Log outputs. `main` hands every entry to each configured sink, collection doesn't know or care which.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Local;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::append::append_log;
+use crate::config::Config;
+use crate::lock::{FileLock, LockConfig};
+use crate::prelude::{Error, Result};
+use crate::provision::{DirLayout, Provisioner};
+use crate::rotation::{FileRotation, FileTemplate, LogFiles};
+use crate::spool::Spool;
+use crate::sqlite_sink::{JournalMode, SqliteSink};
+use crate::text_sink::{TextFormat, TextSink};
+use crate::user_entry::UserEntry;
+use crate::workstation::WorkStationEntry;
+use crate::{history, journal};
+
+/// An output for log entries. `append` may buffer, nothing is guaranteed written until `flush`.
+#[async_trait]
+pub trait LogSink: Send + Sync {
+    /// Unique per config, eg: `xlsx-workstation`.
+    fn id(&self) -> &str;
+
+    /// Up front checks before anything is collected, eg: today's directory exists and is writable.
+    async fn prepare(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn append(&self, entry: &WorkStationEntry) -> Result<()>;
+
+    async fn flush(&self) -> Result<()>;
+
+    /// Whether the sink already has `entry`, asked by the spool about an entry whose last replay was
+    /// cut short somewhere around its delivery. Sinks that skip an entry id they hold anyway can
+    /// leave it `false`.
+    async fn holds(&self, _entry: &WorkStationEntry) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Catch-up work too slow for every logon, eg: leftovers anywhere under the base path
+    /// (`logon_logger log --sweep`).
+    async fn sweep(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Which of the two log layouts a sink writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFamily {
+    /// One file per day for all workstations, `Username` first.
+    Workstation,
+    /// Same data with `ComputerName` heading the first column.
+    User,
+}
+
+impl LogFamily {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogFamily::Workstation => "workstation",
+            LogFamily::User => "user",
+        }
+    }
+
+    /// Falls back to the top level `ws_base_path` / `user_base_path`.
+    fn default_path(self, config: &Config) -> &str {
+        match self {
+            LogFamily::Workstation => &config.ws_base_path,
+            LogFamily::User => &config.user_base_path,
+        }
+    }
+}
+
+/// One `[[sinks]]` entry.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum SinkConfig {
+    Xlsx {
+        family:        LogFamily,
+        /// Directory for the workbooks, defaults to the family's base path.
+        #[serde(default)]
+        path:          Option<String>,
+        /// Subdirectories under `path`, eg: `{year}/{month}`.
+        #[serde(default)]
+        layout:        DirLayout,
+        /// How long one workbook covers.
+        #[serde(default)]
+        rotation:      FileRotation,
+        #[serde(default)]
+        file_name:     FileTemplate,
+        /// Defaults to `xlsx-<family>`.
+        #[serde(default)]
+        id:            Option<String>,
+        /// Rewrite the workbook once its journal holds this many entries, 1 rewrites every time.
+        #[serde(default = "default_compact_after")]
+        compact_after: usize,
+    },
+    Csv {
+        family:    LogFamily,
+        /// Directory for the files, defaults to the family's base path.
+        #[serde(default)]
+        path:      Option<String>,
+        /// Subdirectories under `path`, eg: `{year}/{month}`.
+        #[serde(default)]
+        layout:    DirLayout,
+        /// How long one file covers.
+        #[serde(default)]
+        rotation:  FileRotation,
+        #[serde(default)]
+        file_name: FileTemplate,
+        /// Defaults to `csv-<family>`.
+        #[serde(default)]
+        id:        Option<String>,
+    },
+    Jsonl {
+        family:    LogFamily,
+        /// Directory for the files, defaults to the family's base path.
+        #[serde(default)]
+        path:      Option<String>,
+        /// Subdirectories under `path`, eg: `{year}/{month}`.
+        #[serde(default)]
+        layout:    DirLayout,
+        /// How long one file covers.
+        #[serde(default)]
+        rotation:  FileRotation,
+        #[serde(default)]
+        file_name: FileTemplate,
+        /// Defaults to `jsonl-<family>`.
+        #[serde(default)]
+        id:        Option<String>,
+    },
+    Sqlite {
+        /// The database file, created (and migrated) on first use.
+        path:            PathBuf,
+        #[serde(default)]
+        journal_mode:    JournalMode,
+        /// How long to wait on another writer's lock before failing.
+        #[serde(default = "default_busy_timeout_ms")]
+        busy_timeout_ms: u64,
+        /// Defaults to `sqlite`.
+        #[serde(default)]
+        id:              Option<String>,
+    },
+}
+
+fn default_compact_after() -> usize {
+    25
+}
+
+fn default_busy_timeout_ms() -> u64 {
+    5000
+}
+
+impl SinkConfig {
+    /// What an empty `[[sinks]]` list means: the original workstation + user workbooks.
+    pub fn defaults() -> Vec<SinkConfig> {
+        [LogFamily::Workstation, LogFamily::User]
+            .into_iter()
+            .map(|family| {
+                SinkConfig::Xlsx {
+                    family,
+                    path: None,
+                    layout: DirLayout::default(),
+                    rotation: FileRotation::default(),
+                    file_name: FileTemplate::default(),
+                    id: None,
+                    compact_after: default_compact_after(),
+                }
+            })
+            .collect()
+    }
+
+    /// The `[[sinks]]` list, or the defaults when there is none.
+    pub fn configured(config: &Config) -> Vec<SinkConfig> {
+        if config.sinks.is_empty() {
+            Self::defaults()
+        } else {
+            config.sinks.clone()
+        }
+    }
+
+    /// Checks serde can't make, eg: a journal mode that doesn't work where the database is.
+    pub fn validate(&self, config: &Config) -> Result<()> {
+        match self {
+            SinkConfig::Sqlite {
+                path, journal_mode, ..
+            } => journal_mode.check(&config.resolve_relative(path)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Where a file sink's files go, `None` for the others.
+    pub fn files(&self, config: &Config) -> Result<Option<LogFiles>> {
+        let (extension, family, path, layout, rotation, file_name) = match self {
+            SinkConfig::Xlsx {
+                family,
+                path,
+                layout,
+                rotation,
+                file_name,
+                ..
+            } => ("xlsx", family, path, layout, rotation, file_name),
+            SinkConfig::Csv {
+                family,
+                path,
+                layout,
+                rotation,
+                file_name,
+                ..
+            } => (TextFormat::Csv.extension(), family, path, layout, rotation, file_name),
+            SinkConfig::Jsonl {
+                family,
+                path,
+                layout,
+                rotation,
+                file_name,
+                ..
+            } => (TextFormat::Jsonl.extension(), family, path, layout, rotation, file_name),
+            SinkConfig::Sqlite { .. } => return Ok(None),
+        };
+
+        LogFiles::new(
+            config,
+            PathBuf::from(path.as_deref().unwrap_or(family.default_path(config))),
+            *family,
+            layout.clone(),
+            *rotation,
+            file_name.clone(),
+            extension,
+        )
+        .map(Some)
+    }
+
+    fn build(&self, config: &Config) -> Result<Arc<dyn LogSink>> {
+        let files = self.files(config)?;
+        let sink: Arc<dyn LogSink> = match (self, files) {
+            (
+                SinkConfig::Xlsx {
+                    family,
+                    id,
+                    compact_after,
+                    ..
+                },
+                Some(files),
+            ) => {
+                Arc::new(ExcelSink {
+                    id: id.clone().unwrap_or_else(|| format!("xlsx-{}", family.as_str())),
+                    family: *family,
+                    files,
+                    worksheet_name: config.worksheet_name.clone(),
+                    compact_after: *compact_after,
+                    lock: config.lock.clone(),
+                    provisioner: Provisioner::default(),
+                    pending: Mutex::new(vec![]),
+                })
+            }
+            (SinkConfig::Csv { family, id, .. }, Some(files)) => {
+                text_sink(TextFormat::Csv, *family, files, id, config)
+            }
+            (SinkConfig::Jsonl { family, id, .. }, Some(files)) => {
+                text_sink(TextFormat::Jsonl, *family, files, id, config)
+            }
+            (
+                SinkConfig::Sqlite {
+                    path,
+                    journal_mode,
+                    busy_timeout_ms,
+                    id,
+                },
+                _,
+            ) => {
+                Arc::new(SqliteSink::new(
+                    id.clone().unwrap_or_else(|| "sqlite".to_string()),
+                    config.resolve_relative(path),
+                    *journal_mode,
+                    Duration::from_millis(*busy_timeout_ms),
+                ))
+            }
+            (_, None) => unreachable!("file sinks always have `files`"),
+        };
+        Ok(sink)
+    }
+}
+
+fn text_sink(
+    format: TextFormat,
+    family: LogFamily,
+    files: LogFiles,
+    id: &Option<String>,
+    config: &Config,
+) -> Arc<dyn LogSink> {
+    Arc::new(TextSink::new(
+        id.clone()
+            .unwrap_or_else(|| format!("{}-{}", format.extension(), family.as_str())),
+        family,
+        format,
+        files,
+        config.lock.clone(),
+    ))
+}
+
+/// Builds the configured sinks (or the defaults), rejecting duplicate ids.
+pub fn build_sinks(config: &Config) -> Result<Vec<Arc<dyn LogSink>>> {
+    let sinks = SinkConfig::configured(config)
+        .iter()
+        .map(|s| s.build(config))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut seen = BTreeSet::new();
+    for sink in &sinks {
+        if !seen.insert(sink.id()) {
+            return Err(Error::ConfigError(format!(
+                "Sink id \"{}\" is used more than once, set `id` on one of the `[[sinks]]`",
+                sink.id()
+            )));
+        }
+    }
+    Ok(sinks)
+}
+
+/// Runs every sink's `prepare`, a failure is only logged: the entry still gets its chance (or the spool).
+pub async fn prepare_all(sinks: &[Arc<dyn LogSink>]) {
+    for sink in sinks {
+        if let Err(e) = sink.prepare().await {
+            tracing::warn!("Sink {} isn't ready: {}", sink.id(), e);
+        }
+    }
+}
+
+/// Runs every sink's `sweep`, after the logon is written: a failure is only logged.
+pub async fn sweep_all(sinks: &[Arc<dyn LogSink>]) {
+    for sink in sinks {
+        if let Err(e) = sink.sweep().await {
+            tracing::warn!("Sweeping sink {} failed: {}", sink.id(), e);
+        }
+    }
+}
+
+/// Appends + flushes `entry` on every sink concurrently. A failing sink doesn't stop the others,
+/// its copy of the entry goes to the `spool` (when there is one) for the next run to replay. Only
+/// failures that couldn't be spooled are reported, all together.
+pub async fn write_all(
+    sinks: &[Arc<dyn LogSink>],
+    entry: &WorkStationEntry,
+    spool: Option<&Spool>,
+) -> Result<()> {
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut failed = vec![];
+    for sink in sinks {
+        // Still replaying older entries, this one waits its turn
+        if spool.is_some_and(|spool| spool.is_backlogged(sink.id())) {
+            failed.push(sink.id().to_string());
+            continue;
+        }
+
+        let sink = sink.clone();
+        let entry = entry.clone();
+        tasks.spawn(async move {
+            let result = async {
+                sink.append(&entry).await?;
+                sink.flush().await
+            }
+            .await;
+            (sink.id().to_string(), result)
+        });
+    }
+
+    let mut failures = vec![];
+    while let Some(joined) = tasks.join_next().await {
+        let (id, result) = joined?;
+        if let Err(e) = result {
+            tracing::error!("Sink {} failed: {}", id, e);
+            failures.push(format!("{id}: {e}"));
+            failed.push(id);
+        }
+    }
+
+    if let Some(spool) = spool {
+        failures.clear();
+        for id in failed {
+            match spool.push(&id, entry).await {
+                Ok(()) => tracing::warn!("Spooled the entry for {}, it's replayed on the next run", id),
+                Err(e) => failures.push(format!("{id}: couldn't spool the entry: {e}")),
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::SinkError(failures.join("; ")))
+    }
+}
+
+/// The original output: one workbook per family per day, sorted newest first, fed through a journal.
+pub struct ExcelSink {
+    id:             String,
+    family:         LogFamily,
+    files:          LogFiles,
+    worksheet_name: String,
+    compact_after:  usize,
+    lock:           LockConfig,
+    provisioner:    Provisioner,
+    pending:        Mutex<Vec<WorkStationEntry>>,
+}
+
+#[async_trait]
+impl LogSink for ExcelSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn prepare(&self) -> Result<()> {
+        self.provisioner.ensure(&self.files.dir(&Local::now())).await
+    }
+
+    async fn append(&self, entry: &WorkStationEntry) -> Result<()> {
+        self.pending.lock().await.push(entry.clone());
+        Ok(())
+    }
+
+    /// Entries land in their workbook's journal; the workbook is only rewritten once the journal
+    /// holds `compact_after` entries or its rotation period is over.
+    async fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        let now = Local::now();
+        let current = self.files.path(&now);
+
+        let mut by_file: BTreeMap<PathBuf, Vec<WorkStationEntry>> = BTreeMap::new();
+        for entry in pending.drain(..) {
+            by_file
+                .entry(self.files.path(&entry.date_time))
+                .or_default()
+                .push(entry);
+        }
+
+        let mut files = by_file.into_iter();
+        while let Some((workbook, entries)) = files.next() {
+            // Appending under the workbook's lock too, a compaction could otherwise read (and remove)
+            // the journal between us opening it and writing
+            let appended = async {
+                if let Some(dir) = workbook.parent() {
+                    self.provisioner.ensure(dir).await?;
+                }
+                let lock = FileLock::acquire(&workbook, &self.lock).await?;
+                let held = journal::append(&journal::journal_path(&workbook), &entries).await?;
+                Ok::<_, Error>((lock, held))
+            }
+            .await;
+            let (_lock, held) = match appended {
+                Ok(appended) => appended,
+                // Keep whatever didn't make it to disk for the next flush
+                Err(e) => {
+                    pending.extend(entries);
+                    pending.extend(files.flat_map(|(_, rest)| rest));
+                    return Err(e);
+                }
+            };
+
+            // The entries are safely journaled by now, a failed compaction is retried next time
+            if (workbook != current || held >= self.compact_after)
+                && let Err(e) = self.compact(&workbook).await
+            {
+                tracing::warn!(
+                    "Compacting {} failed, its entries stay in the journal: {}",
+                    workbook.display(),
+                    e
+                );
+            }
+        }
+
+        // Earlier periods nobody has compacted yet, eg: the last logons of yesterday. Only this
+        // period's directory and the last one's are listed, older leftovers wait for a `--sweep`.
+        let mut found = vec![];
+        for dir in self.files.recent_dirs(&now) {
+            match list_dir(&dir).await {
+                Ok(listed) => found.extend(listed),
+                Err(e) => tracing::warn!("Couldn't list journals in {}: {}", dir.display(), e),
+            }
+        }
+        self.compact_leftovers(&found, &current).await;
+
+        Ok(())
+    }
+
+    /// Looks in the journal (by id) and the workbook rows (by content) of `entry`'s workbook.
+    async fn holds(&self, entry: &WorkStationEntry) -> Result<bool> {
+        let workbook = self.files.path(&entry.date_time);
+        let contents = history::read(&workbook, self.family, &self.worksheet_name).await?;
+        Ok(contents.entries.iter().any(|held| held.same_logon(entry)))
+    }
+
+    /// Compacts every leftover journal under the base path, however old its directory.
+    async fn sweep(&self) -> Result<()> {
+        let files = self.files.clone();
+        let found = tokio::task::spawn_blocking(move || files.walk()).await??;
+        self.compact_leftovers(&found, &self.files.path(&Local::now()))
+            .await;
+        Ok(())
+    }
+}
+
+impl ExcelSink {
+    // Compacts the workbooks among `found` other than `current` that have a journal left over: the
+    // last logons of a period stay journaled until someone logs on after it.
+    async fn compact_leftovers(&self, found: &[PathBuf], current: &Path) {
+        let stale = journal::journaled_workbooks(found)
+            .into_iter()
+            .filter(|workbook| {
+                workbook != current
+                    && workbook
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| self.files.is_ours(name))
+            });
+
+        for workbook in stale {
+            let compacted = async {
+                let _lock = FileLock::acquire(&workbook, &self.lock).await?;
+                self.compact(&workbook).await
+            }
+            .await;
+            if let Err(e) = compacted {
+                tracing::warn!(
+                    "Compacting {} failed, its entries stay in the journal: {}",
+                    workbook.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Merges the workbook's journal into it, then drops the journal.
+    /// The caller holds the workbook's lock.
+    async fn compact(&self, workbook: &Path) -> Result<()> {
+        let journal = journal::journal_path(workbook);
+        let compacting = journal::compacting_path(workbook);
+
+        // A leftover from an interrupted compaction goes first, the live journal waits for next time
+        if !tokio::fs::try_exists(&compacting).await? {
+            match tokio::fs::rename(&journal, &compacting).await {
+                Ok(()) => journal::reset_count(&journal).await?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let entries = journal::read(&compacting).await?;
+        match self.family {
+            LogFamily::Workstation => {
+                append_log(&self.worksheet_name, workbook, entries).await?;
+            }
+            LogFamily::User => {
+                let user_entries = entries.into_iter().map(UserEntry::from).collect();
+                append_log(&self.worksheet_name, workbook, user_entries).await?;
+            }
+        }
+        tokio::fs::remove_file(&compacting).await?;
+        Ok(())
+    }
+}
+
+// Files directly in `dir`, none when it doesn't exist (yet).
+async fn list_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut listing = match tokio::fs::read_dir(dir).await {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut found = vec![];
+    while let Some(item) = listing.next_entry().await? {
+        if item.file_type().await?.is_file() {
+            found.push(item.path());
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Days;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn logon_compacts_yesterdays_journal_and_sweep_the_older_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = format!(
+            "[[sinks]]\ntype = \"xlsx\"\nfamily = \"workstation\"\npath = '{}'\nlayout = \"{{year}}/{{month}}/{{day}}\"\n",
+            dir.path().display()
+        );
+        let config = Config::for_test(dir.path(), &toml).unwrap();
+        let sink_config = &SinkConfig::configured(&config)[0];
+        let sink = sink_config.build(&config).unwrap();
+        let files = sink_config.files(&config).unwrap().unwrap();
+
+        let now = Local::now();
+        let journaled = |days_ago| {
+            let then = now.checked_sub_days(Days::new(days_ago)).unwrap();
+            let workbook = files.path(&then);
+            async move {
+                tokio::fs::create_dir_all(workbook.parent().unwrap())
+                    .await
+                    .unwrap();
+                journal::append(
+                    &journal::journal_path(&workbook),
+                    &[WorkStationEntry::sample("jsmith", then)],
+                )
+                .await
+                .unwrap();
+                workbook
+            }
+        };
+        let yesterday = journaled(1).await;
+        let old = journaled(90).await;
+
+        sink.append(&WorkStationEntry::sample("jdoe", now)).await.unwrap();
+        sink.flush().await.unwrap();
+
+        assert!(yesterday.exists());
+        assert!(!journal::journal_path(&yesterday).exists());
+        assert!(!old.exists());
+        assert!(journal::journal_path(&old).exists());
+        assert!(journal::journal_path(&files.path(&now)).exists());
+
+        sink.sweep().await.unwrap();
+
+        assert!(old.exists());
+        assert!(!journal::journal_path(&old).exists());
+        assert!(journal::journal_path(&files.path(&now)).exists());
+    }
+}
+
+#[cfg(test)]
+mod benches {
+    use test::Bencher;
+
+    use super::*;
+
+    // One logon through the `xlsx` sink, its workbook already holding `held` entries. Journaled (the
+    // default), the journal holds as many again and `compact_after` is out of reach; otherwise every
+    // logon rewrites the workbook, as before the journal.
+    fn logon(b: &mut Bencher, held: usize, journaled: bool) {
+        let dir = tempfile::tempdir().unwrap();
+        let compact_after = if journaled { usize::MAX } else { 1 };
+        let toml = format!(
+            "[[sinks]]\ntype = \"xlsx\"\nfamily = \"workstation\"\npath = '{}'\ncompact_after = {compact_after}\n",
+            dir.path().display()
+        );
+        let config = Config::for_test(dir.path(), &toml).unwrap();
+        let sink_config = &SinkConfig::configured(&config)[0];
+        let sink = sink_config.build(&config).unwrap();
+
+        let now = Local::now();
+        let workbook = sink_config.files(&config).unwrap().unwrap().path(&now);
+        let history: Vec<WorkStationEntry> = (0..held)
+            .map(|i| WorkStationEntry::sample(&format!("student{i}"), now))
+            .collect();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            sink.prepare().await.unwrap();
+            if held > 0 {
+                append_log(&config.worksheet_name, &workbook, history.clone())
+                    .await
+                    .unwrap();
+            }
+            if journaled {
+                journal::append(&journal::journal_path(&workbook), &history)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let entry = WorkStationEntry::sample("jdoe", now);
+        b.iter(|| {
+            runtime.block_on(async {
+                sink.append(&entry).await.unwrap();
+                sink.flush().await.unwrap();
+            })
+        });
+    }
+
+    #[bench]
+    fn journaled_logon_new_workbook(b: &mut Bencher) {
+        logon(b, 0, true);
+    }
+
+    #[bench]
+    fn journaled_logon_5000_entries(b: &mut Bencher) {
+        logon(b, 5000, true);
+    }
+
+    #[bench]
+    fn rewriting_logon_100_entries(b: &mut Bencher) {
+        logon(b, 100, false);
+    }
+
+    #[bench]
+    fn rewriting_logon_1000_entries(b: &mut Bencher) {
+        logon(b, 1000, false);
+    }
+}
@@ -0,0 +1,428 @@
+//! Local queue for entries a sink couldn't take (eg: the share is down), replayed on the next run.
+//!
+//! Each queued entry is one `<spool>/<sink id>/<logon millis>-<entry id>.json`, so a directory
+//! listing gives replay order. The ledger, `<spool>/delivered`, gets a line before an entry is handed
+//! to its sink and another once the sink has it, both before its file is removed. An entry recorded
+//! as delivered isn't replayed again; one only recorded as being sent (the run died around its
+//! delivery) is replayed only if the sink says it doesn't hold it yet, see `LogSink::holds`.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Config;
+use crate::prelude::{Error, Result};
+use crate::sink::LogSink;
+use crate::workstation::WorkStationEntry;
+
+const LEDGER_FILE: &str = "delivered";
+// Ends a ledger line written before the entry is handed over, entry ids never end in it.
+const SENDING_MARK: &str = "\t?";
+
+/// `[spool]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpoolConfig {
+    pub enabled: bool,
+    /// Must be local to the machine. Defaults to `%ProgramData%\LogonLogger\spool` on Windows,
+    /// `$XDG_STATE_HOME/logon_logger/spool` elsewhere.
+    pub dir:     Option<PathBuf>,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir:     None,
+        }
+    }
+}
+
+fn default_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("LogonLogger").join("spool"))
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+            .map(|state| state.join("logon_logger").join("spool"))
+    }
+}
+
+pub struct Spool {
+    dir:        PathBuf,
+    /// Sinks with entries still queued, new entries for them queue up behind rather than jump ahead.
+    backlogged: BTreeSet<String>,
+}
+
+impl Spool {
+    /// `None` when disabled, or there's nowhere to put it.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.spool.enabled {
+            return None;
+        }
+        let Some(dir) = config
+            .spool
+            .dir
+            .as_deref()
+            .map(|dir| config.resolve_relative(dir))
+            .or_else(default_dir)
+        else {
+            tracing::warn!("No spool directory (set `spool.dir`), failed entries won't be retried");
+            return None;
+        };
+        Some(Self {
+            dir,
+            backlogged: BTreeSet::new(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Sink ids are free text, keep them to something every filesystem takes.
+    fn sink_dir(&self, sink_id: &str) -> PathBuf {
+        let name: String = sink_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(name)
+    }
+
+    pub fn is_backlogged(&self, sink_id: &str) -> bool {
+        self.backlogged.contains(sink_id)
+    }
+
+    /// Queues `entry` for `sink_id`.
+    pub async fn push(&self, sink_id: &str, entry: &WorkStationEntry) -> Result<()> {
+        let dir = self.sink_dir(sink_id);
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let name = format!("{:013}-{}.json", entry.date_time.timestamp_millis(), entry.entry_id);
+        let record = serde_json::to_vec(entry).map_err(|e| Error::Generic(e.to_string()))?;
+
+        // Written aside then renamed in, a half written record is never picked up
+        let tmp = dir.join(format!("{name}.tmp"));
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        file.write_all(&record).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&tmp, dir.join(name)).await?;
+        Ok(())
+    }
+
+    /// Replays queued entries into `sinks`, oldest first. A sink that fails again keeps the rest of
+    /// its queue (and is marked backlogged), the others carry on.
+    pub async fn drain(&mut self, sinks: &[Arc<dyn LogSink>]) {
+        let mut ledger = match read_ledger(&self.dir).await {
+            Ok(ledger) => ledger,
+            Err(e) => {
+                tracing::warn!("Couldn't read the spool ledger, not replaying anything: {}", e);
+                self.backlogged = sinks.iter().map(|sink| sink.id().to_string()).collect();
+                return;
+            }
+        };
+
+        for sink in sinks {
+            match self.drain_sink(sink.as_ref(), &mut ledger).await {
+                Ok(0) => {}
+                Ok(replayed) => tracing::info!("Replayed {} spooled entries into {}", replayed, sink.id()),
+                Err(e) => {
+                    tracing::warn!(
+                        "Replaying spooled entries into {} failed, will retry next run: {}",
+                        sink.id(),
+                        e
+                    );
+                    self.backlogged.insert(sink.id().to_string());
+                }
+            }
+        }
+
+        // Every replayed file is gone by now, the ledger only has to outlive them
+        if self.backlogged.is_empty()
+            && !(ledger.sending.is_empty() && ledger.delivered.is_empty())
+            && let Err(e) = tokio::fs::remove_file(self.dir.join(LEDGER_FILE)).await
+        {
+            tracing::warn!("Couldn't clear the spool ledger: {}", e);
+        }
+    }
+
+    async fn drain_sink(&self, sink: &dyn LogSink, ledger: &mut Ledger) -> Result<usize> {
+        let records = list_records(&self.sink_dir(sink.id())).await?;
+
+        let mut replayed = 0;
+        for path in records {
+            let entry: WorkStationEntry = match tokio::fs::read(&path)
+                .await
+                .map_err(Error::from)
+                .and_then(|raw| serde_json::from_slice(&raw).map_err(|e| Error::Generic(e.to_string())))
+            {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // Moved aside, one bad record mustn't hold up the queue forever
+                    tracing::error!("Unreadable spool record {}, moving it aside: {}", path.display(), e);
+                    tokio::fs::rename(&path, path.with_extension("bad")).await?;
+                    continue;
+                }
+            };
+
+            let key = ledger_key(sink.id(), &entry);
+            if !ledger.delivered.contains(&key) {
+                // Sent before but not recorded as delivered, the sink may or may not have taken it
+                let held = ledger.sending.contains(&key) && sink.holds(&entry).await?;
+                if !held {
+                    append_ledger(&self.dir, &format!("{key}{SENDING_MARK}")).await?;
+                    ledger.sending.insert(key.clone());
+                    sink.append(&entry).await?;
+                    sink.flush().await?;
+                    replayed += 1;
+                }
+                append_ledger(&self.dir, &key).await?;
+                ledger.delivered.insert(key);
+            }
+            tokio::fs::remove_file(&path).await?;
+        }
+        Ok(replayed)
+    }
+}
+
+fn ledger_key(sink_id: &str, entry: &WorkStationEntry) -> String {
+    format!("{sink_id}\t{}", entry.entry_id)
+}
+
+/// What the ledger says about each `sink id\tentry id` key.
+#[derive(Debug, Default)]
+struct Ledger {
+    /// About to be handed to their sink, maybe they were.
+    sending:   BTreeSet<String>,
+    /// Taken by their sink.
+    delivered: BTreeSet<String>,
+}
+
+async fn read_ledger(dir: &Path) -> Result<Ledger> {
+    let text = match tokio::fs::read_to_string(dir.join(LEDGER_FILE)).await {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Ledger::default()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut ledger = Ledger::default();
+    for line in text.lines() {
+        match line.strip_suffix(SENDING_MARK) {
+            Some(key) => ledger.sending.insert(key.to_string()),
+            None => ledger.delivered.insert(line.to_string()),
+        };
+    }
+    Ok(ledger)
+}
+
+async fn append_ledger(dir: &Path, key: &str) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LEDGER_FILE))
+        .await?;
+    file.write_all(format!("{key}\n").as_bytes()).await?;
+    file.sync_all().await?;
+    Ok(())
+}
+
+// Queued records for one sink, oldest first.
+async fn list_records(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut listing = match tokio::fs::read_dir(dir).await {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = vec![];
+    while let Some(item) = listing.next_entry().await? {
+        let path = item.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            records.push(path);
+        }
+    }
+    records.sort();
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use chrono::{Duration, Local};
+
+    use super::*;
+    use crate::sink::{LogFamily, SinkConfig, build_sinks};
+    use crate::{history, journal};
+
+    // Takes entries into memory, or fails every flush.
+    struct Recording {
+        failing: bool,
+        taken:   Mutex<Vec<WorkStationEntry>>,
+    }
+
+    #[async_trait]
+    impl LogSink for Recording {
+        fn id(&self) -> &str {
+            "recording"
+        }
+
+        async fn append(&self, entry: &WorkStationEntry) -> Result<()> {
+            self.taken.lock().unwrap().push(entry.clone());
+            Ok(())
+        }
+
+        async fn flush(&self) -> Result<()> {
+            if self.failing {
+                return Err(Error::SinkError("share unreachable".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    fn recording(failing: bool) -> Arc<Recording> {
+        Arc::new(Recording {
+            failing,
+            taken: Mutex::new(vec![]),
+        })
+    }
+
+    fn spool(dir: &Path) -> Spool {
+        Spool {
+            dir:        dir.join("spool"),
+            backlogged: BTreeSet::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_oldest_first_and_empties_the_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = spool(dir.path());
+        let now = Local::now();
+        let later = WorkStationEntry::sample("later", now);
+        let earlier = WorkStationEntry::sample("earlier", now - Duration::minutes(5));
+        spool.push("recording", &later).await.unwrap();
+        spool.push("recording", &earlier).await.unwrap();
+
+        let sink = recording(false);
+        spool.drain(&[sink.clone() as Arc<dyn LogSink>]).await;
+
+        let taken: Vec<String> = sink
+            .taken
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| e.username.clone())
+            .collect();
+        assert_eq!(taken, ["earlier", "later"]);
+        assert!(!spool.is_backlogged("recording"));
+        assert!(
+            list_records(&spool.sink_dir("recording"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!spool.dir().join(LEDGER_FILE).exists());
+    }
+
+    #[tokio::test]
+    async fn sink_failing_again_keeps_its_queue_and_is_backlogged() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = spool(dir.path());
+        spool
+            .push("recording", &WorkStationEntry::sample("jsmith", Local::now()))
+            .await
+            .unwrap();
+
+        spool.drain(&[recording(true) as Arc<dyn LogSink>]).await;
+
+        assert!(spool.is_backlogged("recording"));
+        assert_eq!(list_records(&spool.sink_dir("recording")).await.unwrap().len(), 1);
+    }
+
+    // A run that died after the sink took the entry but before the ledger said so, for each file sink.
+    #[tokio::test]
+    async fn replay_after_a_crash_past_delivery_doesnt_write_the_entry_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut toml = String::new();
+        for kind in ["xlsx", "csv", "jsonl"] {
+            toml.push_str(&format!(
+                "[[sinks]]\ntype = \"{kind}\"\nfamily = \"workstation\"\npath = '{}'\n\n",
+                dir.path().join(kind).display()
+            ));
+        }
+        // Compacted straight away, so the entry is only in the workbook rows by the time it's replayed
+        toml.push_str(&format!(
+            "[[sinks]]\ntype = \"xlsx\"\nfamily = \"workstation\"\npath = '{}'\nid = \"compacted\"\ncompact_after = 1\n",
+            dir.path().join("compacted").display()
+        ));
+        let config = Config::for_test(dir.path(), &toml).unwrap();
+        let sinks = build_sinks(&config).unwrap();
+        let mut spool = spool(dir.path());
+
+        let delivered = WorkStationEntry::sample("delivered", Local::now());
+        let not_yet = WorkStationEntry::sample("not-yet", Local::now());
+        for sink in &sinks {
+            for entry in [&delivered, &not_yet] {
+                spool.push(sink.id(), entry).await.unwrap();
+                append_ledger(&spool.dir, &format!("{}{SENDING_MARK}", ledger_key(sink.id(), entry)))
+                    .await
+                    .unwrap();
+            }
+            sink.append(&delivered).await.unwrap();
+            sink.flush().await.unwrap();
+        }
+
+        spool.drain(&sinks).await;
+
+        for (sink, sink_config) in sinks.iter().zip(SinkConfig::configured(&config)) {
+            assert!(!spool.is_backlogged(sink.id()), "{}", sink.id());
+            let path = sink_config
+                .files(&config)
+                .unwrap()
+                .unwrap()
+                .path(&delivered.date_time);
+            let users: Vec<String> = if sink.id() == "compacted" {
+                let contents = history::read(&path, LogFamily::Workstation, &config.worksheet_name)
+                    .await
+                    .unwrap();
+                let mut users: Vec<String> = contents.entries.into_iter().map(|e| e.username).collect();
+                users.sort();
+                users
+            } else if sink.id().starts_with("xlsx") {
+                journal::read(&journal::journal_path(&path))
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|e| e.username)
+                    .collect()
+            } else {
+                let text = std::fs::read_to_string(&path).unwrap();
+                ["delivered", "not-yet"]
+                    .into_iter()
+                    .flat_map(|user| {
+                        // Once per line, by the user's mail address
+                        let lines = text
+                            .lines()
+                            .filter(|line| line.contains(&format!("{user}@")))
+                            .count();
+                        std::iter::repeat_n(user.to_string(), lines)
+                    })
+                    .collect()
+            };
+            assert_eq!(users, ["delivered", "not-yet"], "{}", sink.id());
+        }
+    }
+}
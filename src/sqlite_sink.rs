@@ -0,0 +1,332 @@
+//! SQLite sink, one `logons` row per entry so months of logons can be queried in one place.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use rusqlite::{Connection, params};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::prelude::{Error, Result};
+use crate::provision::Provisioner;
+use crate::sink::LogSink;
+use crate::workstation::WorkStationEntry;
+
+/// Applied in order on open, `PRAGMA user_version` records how many already ran.
+/// Append only, never edit a migration that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE logons (
+        id              INTEGER PRIMARY KEY,
+        username        TEXT NOT NULL,
+        computer        TEXT NOT NULL,
+        logged_at       INTEGER NOT NULL,
+        logged_at_local TEXT NOT NULL,
+        user_ou         TEXT NOT NULL,
+        ws_ou           TEXT NOT NULL,
+        full_ou         TEXT NOT NULL,
+        period          TEXT NOT NULL,
+        schedule        TEXT NOT NULL,
+        description     TEXT NOT NULL,
+        os              TEXT NOT NULL,
+        os_version      TEXT NOT NULL,
+        make            TEXT NOT NULL,
+        model           TEXT NOT NULL,
+        uuid            TEXT NOT NULL,
+        serial_number   TEXT NOT NULL,
+        department      TEXT NOT NULL,
+        title           TEXT NOT NULL,
+        mail            TEXT NOT NULL,
+        member_of       TEXT NOT NULL
+    );
+    CREATE INDEX logons_username ON logons (username);
+    CREATE INDEX logons_computer ON logons (computer);
+    CREATE INDEX logons_logged_at ON logons (logged_at);
+    CREATE INDEX logons_uuid ON logons (uuid);",
+    // 2: entry ids, so a replayed entry (see `spool`) isn't inserted twice. Older rows stay NULL
+    "ALTER TABLE logons ADD COLUMN entry_id TEXT;
+    CREATE UNIQUE INDEX logons_entry_id ON logons (entry_id);",
+];
+
+/// `PRAGMA journal_mode` for the database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JournalMode {
+    /// Readers don't block the writer. Needs every writer on the same host (no SMB shares).
+    Wal,
+    /// Rollback journal, the safe choice for a database on a network share.
+    #[default]
+    Delete,
+}
+
+impl JournalMode {
+    /// WAL's shared-memory index only works with every connection on one host, so it's refused for
+    /// a UNC path. A mapped drive looks local and can't be caught here.
+    pub fn check(self, path: &Path) -> Result<()> {
+        let raw = path.to_string_lossy();
+        if self == JournalMode::Wal && (raw.starts_with(r"\\") || raw.starts_with("//")) {
+            return Err(Error::ConfigError(format!(
+                "`journal_mode = \"wal\"` can't be used for {}, WAL corrupts a database on a network share, use \"delete\"",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JournalMode::Wal => "WAL",
+            JournalMode::Delete => "DELETE",
+        }
+    }
+}
+
+pub struct SqliteSink {
+    id:           String,
+    path:         PathBuf,
+    journal_mode: JournalMode,
+    busy_timeout: Duration,
+    provisioner:  Provisioner,
+    pending:      Mutex<Vec<WorkStationEntry>>,
+}
+
+impl SqliteSink {
+    pub fn new(id: String, path: PathBuf, journal_mode: JournalMode, busy_timeout: Duration) -> Self {
+        Self {
+            id,
+            path,
+            journal_mode,
+            busy_timeout,
+            provisioner: Provisioner::default(),
+            pending: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl SqliteSink {
+    async fn ensure_dir(&self) -> Result<()> {
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => self.provisioner.ensure(dir).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl LogSink for SqliteSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn prepare(&self) -> Result<()> {
+        self.ensure_dir().await
+    }
+
+    async fn append(&self, entry: &WorkStationEntry) -> Result<()> {
+        self.pending.lock().await.push(entry.clone());
+        Ok(())
+    }
+
+    /// All pending entries go in as one transaction, on failure they're kept for the next flush.
+    async fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.ensure_dir().await?;
+
+        let entries = pending.clone();
+        let path = self.path.clone();
+        let journal_mode = self.journal_mode;
+        let busy_timeout = self.busy_timeout;
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = open(&path, journal_mode, busy_timeout)?;
+            insert(&mut conn, &entries)
+        })
+        .await??;
+
+        pending.clear();
+        Ok(())
+    }
+}
+
+/// Opens (creating if needed) and migrates the database, its directory must already exist.
+fn open(path: &Path, journal_mode: JournalMode, busy_timeout: Duration) -> Result<Connection> {
+    let mut conn = Connection::open(path)?;
+    // Before anything that might take a lock, other writers may be mid-transaction
+    conn.busy_timeout(busy_timeout)?;
+    conn.pragma_update(None, "journal_mode", journal_mode.as_str())?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let version: u32 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if version as usize > MIGRATIONS.len() {
+        return Err(Error::SinkError(format!(
+            "Database schema is version {version}, this build only knows up to {}",
+            MIGRATIONS.len()
+        )));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as u32)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn insert(conn: &mut Connection, entries: &[WorkStationEntry]) -> Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR IGNORE INTO logons (
+                username, computer, logged_at, logged_at_local, user_ou, ws_ou, full_ou, period, schedule,
+                description, os, os_version, make, model, uuid, serial_number, department, title, mail,
+                member_of, entry_id
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+        )?;
+        for e in entries {
+            stmt.execute(params![
+                e.username,
+                e.computer_name,
+                e.date_time.timestamp(),
+                e.date_time.to_rfc3339_opts(SecondsFormat::Secs, false),
+                e.user_ou,
+                e.ws_ou,
+                e.full_ou,
+                e.period,
+                e.schedule,
+                e.description,
+                e.os,
+                e.os_version,
+                e.make,
+                e.model,
+                e.uuid,
+                e.serial_number,
+                e.department,
+                e.title,
+                e.mail,
+                e.member_of,
+                e.entry_id.to_string(),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+
+    fn sink(path: &Path) -> SqliteSink {
+        SqliteSink::new("sqlite".to_string(), path.to_path_buf(), JournalMode::Delete, Duration::from_secs(1))
+    }
+
+    fn version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    fn rows(path: &Path) -> u32 {
+        Connection::open(path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM logons", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn new_database_gets_every_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = open(&dir.path().join("logons.db"), JournalMode::Delete, Duration::from_secs(1)).unwrap();
+        assert_eq!(version(&conn) as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn v1_database_is_migrated_keeping_its_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logons.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(MIGRATIONS[0]).unwrap();
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            conn.execute(
+                "INSERT INTO logons VALUES
+                    (1, 'jsmith', 'LIB-PC-14', 0, '', '', '', '', '', '', '', '', '', '', '', '', '', '', '', '', '')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let conn = open(&path, JournalMode::Delete, Duration::from_secs(1)).unwrap();
+        assert_eq!(version(&conn), 2);
+        let entry_id: Option<String> = conn
+            .query_row("SELECT entry_id FROM logons WHERE username = 'jsmith'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(entry_id, None);
+
+        // And reopening a migrated database leaves it be
+        drop(conn);
+        let conn = open(&path, JournalMode::Delete, Duration::from_secs(1)).unwrap();
+        assert_eq!(version(&conn), 2);
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logons.db");
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+            .unwrap();
+
+        let err = open(&path, JournalMode::Delete, Duration::from_secs(1)).unwrap_err();
+        assert!(matches!(err, Error::SinkError(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn replayed_entry_is_inserted_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logons.db");
+        let sink = sink(&path);
+        let entry = WorkStationEntry::sample("jsmith", Local::now());
+
+        for e in [&entry, &entry, &WorkStationEntry::sample("jdoe", Local::now())] {
+            sink.append(e).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+        sink.append(&entry).await.unwrap();
+        sink.flush().await.unwrap();
+
+        assert_eq!(rows(&path), 2);
+    }
+
+    #[test]
+    fn wal_is_refused_on_a_unc_path() {
+        assert!(
+            JournalMode::Wal
+                .check(Path::new(r"\\Server\Logs$\logons.db"))
+                .is_err()
+        );
+        assert!(
+            JournalMode::Wal
+                .check(Path::new("//server/logs/logons.db"))
+                .is_err()
+        );
+        assert!(JournalMode::Wal.check(Path::new(r"D:\Logs\logons.db")).is_ok());
+        assert!(
+            JournalMode::Delete
+                .check(Path::new(r"\\Server\Logs$\logons.db"))
+                .is_ok()
+        );
+    }
+}
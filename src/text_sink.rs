@@ -0,0 +1,298 @@
+//! Append-only text sinks (CSV, JSON Lines). The workstation family's columns and their order come
+//! from `ExcelLoggable::COLUMNS`, the user family's from `UserEntry::TEXT_COLUMNS`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::Local;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::ExcelLoggable;
+use crate::lock::{FileLock, LockConfig};
+use crate::prelude::Result;
+use crate::provision::Provisioner;
+use crate::rotation::LogFiles;
+use crate::sink::{LogFamily, LogSink};
+use crate::user_entry::UserEntry;
+use crate::workstation::WorkStationEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// RFC 4180, header row written when the file is created.
+    Csv,
+    /// One JSON object per line, keys are the column names.
+    Jsonl,
+}
+
+impl TextFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TextFormat::Csv => "csv",
+            TextFormat::Jsonl => "jsonl",
+        }
+    }
+
+    fn header(self, columns: &[&str]) -> Option<String> {
+        match self {
+            TextFormat::Csv => Some(csv_line(columns)),
+            TextFormat::Jsonl => None,
+        }
+    }
+
+    fn line(self, columns: &[&str], values: &[String]) -> String {
+        match self {
+            TextFormat::Csv => csv_line(values),
+            TextFormat::Jsonl => jsonl_line(columns, values),
+        }
+    }
+}
+
+/// `<family>_log_<key>.<ext>` files (see `LogFiles`), entries are appended, never rewritten.
+pub struct TextSink {
+    id:          String,
+    family:      LogFamily,
+    format:      TextFormat,
+    files:       LogFiles,
+    lock:        LockConfig,
+    provisioner: Provisioner,
+    pending:     Mutex<Vec<WorkStationEntry>>,
+}
+
+impl TextSink {
+    pub fn new(id: String, family: LogFamily, format: TextFormat, files: LogFiles, lock: LockConfig) -> Self {
+        Self {
+            id,
+            family,
+            format,
+            files,
+            lock,
+            provisioner: Provisioner::default(),
+            pending: Mutex::new(vec![]),
+        }
+    }
+
+    // Whole lines for `entries` in the family's layout.
+    fn render(&self, entries: &[WorkStationEntry]) -> (Option<String>, String) {
+        let columns = match self.family {
+            LogFamily::Workstation => WorkStationEntry::COLUMNS,
+            LogFamily::User => UserEntry::TEXT_COLUMNS,
+        };
+        let body = entries
+            .iter()
+            .map(|entry| {
+                let values = match self.family {
+                    LogFamily::Workstation => entry.field_values(),
+                    LogFamily::User => UserEntry::from(entry.clone()).text_values(),
+                };
+                self.format.line(columns, &values)
+            })
+            .collect();
+        (self.format.header(columns), body)
+    }
+
+    async fn append_file(&self, path: &Path, header: Option<&str>, body: &str) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            self.provisioner.ensure(parent).await?;
+        }
+        // Under the file's lock: two first writers would otherwise both find it empty and both write
+        // the header, and retention mustn't zip and delete it mid-append
+        let _lock = FileLock::acquire(path, &self.lock).await?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        // One write per flush, so concurrent writers interleave whole batches rather than partial lines
+        let mut out = String::new();
+        if let Some(header) = header
+            && file.metadata().await?.len() == 0
+        {
+            out.push_str(header);
+        }
+        out.push_str(body);
+        file.write_all(out.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LogSink for TextSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn prepare(&self) -> Result<()> {
+        self.provisioner.ensure(&self.files.dir(&Local::now())).await
+    }
+
+    async fn append(&self, entry: &WorkStationEntry) -> Result<()> {
+        self.pending.lock().await.push(entry.clone());
+        Ok(())
+    }
+
+    /// Looks for `entry`'s exact line in its file.
+    async fn holds(&self, entry: &WorkStationEntry) -> Result<bool> {
+        let written = match tokio::fs::read_to_string(self.files.path(&entry.date_time)).await {
+            Ok(written) => written,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let (_, line) = self.render(std::slice::from_ref(entry));
+        Ok(written.starts_with(&line) || written.contains(&format!("\n{line}")))
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+
+        let mut by_file: BTreeMap<PathBuf, Vec<WorkStationEntry>> = BTreeMap::new();
+        for entry in pending.drain(..) {
+            by_file
+                .entry(self.files.path(&entry.date_time))
+                .or_default()
+                .push(entry);
+        }
+
+        let mut files = by_file.into_iter();
+        while let Some((path, entries)) = files.next() {
+            let (header, body) = self.render(&entries);
+            // Keep whatever didn't make it to disk for the next flush
+            if let Err(e) = self.append_file(&path, header.as_deref(), &body).await {
+                pending.extend(entries);
+                pending.extend(files.flat_map(|(_, rest)| rest));
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// RFC 4180: fields with `,` `"` CR or LF are quoted, quotes doubled, CRLF line ends.
+pub fn csv_line(values: &[impl AsRef<str>]) -> String {
+    let mut line = values
+        .iter()
+        .map(|v| {
+            let v = v.as_ref();
+            if v.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", v.replace('"', "\"\""))
+            } else {
+                v.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+// Built by hand to keep the keys in column order (serde_json's map would sort them).
+pub fn jsonl_line(columns: &[&str], values: &[String]) -> String {
+    let fields = columns
+        .iter()
+        .zip(values)
+        .map(|(column, value)| {
+            format!("{}:{}", serde_json::Value::from(*column), serde_json::Value::from(value.as_str()))
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{{fields}}}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sink::{SinkConfig, build_sinks};
+
+    #[test]
+    fn csv_line_quotes_only_what_needs_it() {
+        assert_eq!(csv_line(&["plain", "LIB-PC-14", ""]), "plain,LIB-PC-14,\r\n");
+        assert_eq!(
+            csv_line(&["Smith, John", "say \"hi\"", "two\r\nlines", "lf\nonly"]),
+            "\"Smith, John\",\"say \"\"hi\"\"\",\"two\r\nlines\",\"lf\nonly\"\r\n"
+        );
+    }
+
+    #[test]
+    fn jsonl_line_escapes_values_and_keeps_column_order() {
+        let columns = ["Username", "Description", "Path"];
+        let values = [
+            "O\"Brien".to_string(),
+            "line one\nline two\ttab \u{1}".to_string(),
+            r"C:\Users\Public".to_string(),
+        ];
+
+        let line = jsonl_line(&columns, &values);
+        assert!(line.ends_with("}\n") && !line.trim_end().contains('\n'));
+        assert!(line.starts_with("{\"Username\":\"O\\\"Brien\",\"Description\":"), "{line}");
+
+        let parsed: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.keys().collect::<Vec<_>>(), ["Description", "Path", "Username"]);
+        for (column, value) in columns.iter().zip(&values) {
+            assert_eq!(parsed[*column], serde_json::Value::from(value.as_str()));
+        }
+    }
+
+    fn config(dir: &Path, kind: &str, family: &str) -> Config {
+        let toml =
+            format!("[[sinks]]\ntype = \"{kind}\"\nfamily = \"{family}\"\npath = '{}'\n", dir.display());
+        Config::for_test(dir, &toml).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn first_writers_racing_write_one_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), "csv", "workstation");
+        let now = Local::now();
+
+        // Many logons' sinks, directory already provisioned (the write probe is per process), all
+        // flushing into the same new file at once
+        let mut writers = tokio::task::JoinSet::new();
+        for i in 0..32 {
+            let sink = build_sinks(&config).unwrap().remove(0);
+            sink.prepare().await.unwrap();
+            writers.spawn(async move {
+                sink.append(&WorkStationEntry::sample(&format!("student{i}"), now))
+                    .await
+                    .unwrap();
+                sink.flush().await.unwrap();
+            });
+        }
+        writers.join_all().await;
+
+        let path = SinkConfig::configured(&config)[0]
+            .files(&config)
+            .unwrap()
+            .unwrap()
+            .path(&now);
+        let written = std::fs::read_to_string(path).unwrap();
+        assert!(written.starts_with("Username,UserOU,DateTime,"));
+        assert_eq!(written.matches("Username,UserOU").count(), 1);
+        assert_eq!(written.lines().count(), 33);
+    }
+
+    #[tokio::test]
+    async fn user_family_values_line_up_with_their_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), "jsonl", "user");
+        let sink = build_sinks(&config).unwrap().remove(0);
+        let entry = WorkStationEntry::sample("jsmith", Local::now());
+        sink.append(&entry).await.unwrap();
+        sink.flush().await.unwrap();
+
+        let path = SinkConfig::configured(&config)[0]
+            .files(&config)
+            .unwrap()
+            .unwrap()
+            .path(&entry.date_time);
+        let written = std::fs::read_to_string(path).unwrap();
+        assert!(written.starts_with("{\"ComputerName\":\"LIB-PC-14\",\"Username\":\"jsmith\","), "{written}");
+        let parsed: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&written).unwrap();
+        assert_eq!(parsed.len(), UserEntry::TEXT_COLUMNS.len());
+        assert_eq!(parsed["Mail"], "jsmith@school.local");
+    }
+}
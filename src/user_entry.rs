@@ -25,6 +25,12 @@ impl From<WorkStationEntry> for UserEntry {
     }
 }
 
+impl From<UserEntry> for WorkStationEntry {
+    fn from(entry: UserEntry) -> Self {
+        entry.workstation_entry
+    }
+}
+
 impl Deref for UserEntry {
     type Target = WorkStationEntry;
 
@@ -33,6 +39,42 @@ impl Deref for UserEntry {
     }
 }
 
+impl UserEntry {
+    /// The user layout for the sinks without a workbook's history (csv, jsonl): the computer first,
+    /// then the username and the rest as in `WorkStationEntry::COLUMNS`. The workbooks keep their
+    /// original layout, where `ComputerName` heads the username's column.
+    pub const TEXT_COLUMNS: &'static [&'static str] = &[
+        "ComputerName",
+        "Username",
+        "UserOU",
+        "DateTime",
+        "Period",
+        "Description",
+        "WS_OU",
+        "OSVersion",
+        "Model",
+        "OS",
+        "Full_OU",
+        "Make",
+        "UUID",
+        "Serial_Number",
+        "Schedule",
+        "Department",
+        "Title",
+        "Mail",
+        "MemberOf",
+    ];
+
+    /// One string per `TEXT_COLUMNS` entry, same order.
+    pub fn text_values(&self) -> Vec<String> {
+        let mut values = self.workstation_entry.field_values();
+        // `Computer` (last) moves to the front
+        let computer = values.pop().unwrap_or_default();
+        values.insert(0, computer);
+        values
+    }
+}
+
 impl ExcelLoggable for UserEntry {
     const COLUMNS: &'static [&'static str] = &[
         "ComputerName",
@@ -48,12 +90,22 @@ impl ExcelLoggable for UserEntry {
         "Make",
         "UUID",
         "Serial_Number",
+        "Schedule",
+        "Department",
+        "Title",
+        "Mail",
+        "MemberOf",
+        "Computer",
     ];
 
     fn write_entry(&self, ws: &mut rust_xlsxwriter::worksheet::Worksheet, row: u32) -> Result<()> {
         self.workstation_entry.write_entry(ws, row)
     }
 
+    fn field_values(&self) -> Vec<String> {
+        self.workstation_entry.field_values()
+    }
+
     fn parse_row(row: &[Data]) -> Option<Self> {
         Some(Self {
             workstation_entry: WorkStationEntry::parse_row(row)?,
@@ -1,14 +1,16 @@
 use calamine::{Data, DataType};
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, SecondsFormat, TimeDelta, TimeZone};
 use rust_xlsxwriter::ExcelDateTime;
 use rust_xlsxwriter::worksheet::Worksheet;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::collect::{BaseInfo, HardwareInfo, OsInfo};
-use crate::period::{PERIODS, get_current_period};
+use crate::period::{ResolvedPeriod, Schedule, get_current_period};
 use crate::{Error, ExcelLoggable, FieldLengsths, HasDateTime, Result};
 
 // TODO: [trait] : Better to do this via like, S: FromStr or Into<str> or something
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct WorkStationEntry {
     pub username:      String,
     pub user_ou:       String,
@@ -23,18 +25,32 @@ pub struct WorkStationEntry {
     pub make:          String,
     pub uuid:          String,
     pub serial_number: String,
+    pub schedule:      String,
+    pub department:    String,
+    pub title:         String,
+    pub mail:          String,
+    pub member_of:     String,
+    pub computer_name: String,
+    /// Identifies this logon across retries (the spool, SQLite's dedup), not written to the workbooks.
+    #[serde(default = "Uuid::new_v4")]
+    pub entry_id:      Uuid,
 }
 
-impl From<(BaseInfo, HardwareInfo, OsInfo, DateTime<Local>)> for WorkStationEntry {
-    fn from(value: (BaseInfo, HardwareInfo, OsInfo, DateTime<Local>)) -> Self {
-        let (base, hardware, os, now) = value;
+impl From<(BaseInfo, HardwareInfo, OsInfo, DateTime<Local>, &Schedule)> for WorkStationEntry {
+    fn from(value: (BaseInfo, HardwareInfo, OsInfo, DateTime<Local>, &Schedule)) -> Self {
+        let (base, hardware, os, now, schedule) = value;
         // let now = chrono::Local::now();
-        let period_value = get_current_period(&now, &PERIODS).unwrap_or_else(|_| "Unknown".to_string());
+        let period = get_current_period(&now, schedule).unwrap_or_else(|_| {
+            ResolvedPeriod {
+                name:    "Unknown".to_string(),
+                variant: "Unknown".to_string(),
+            }
+        });
         Self {
             username:      base.username,
             user_ou:       base.user_ou,
             date_time:     now,
-            period:        period_value,
+            period:        period.name,
             description:   hardware.os_description,
             ws_ou:         base.ws_ou,
             os_version:    os.os_version,
@@ -44,10 +60,69 @@ impl From<(BaseInfo, HardwareInfo, OsInfo, DateTime<Local>)> for WorkStationEntr
             make:          hardware.make,
             uuid:          hardware.uuid,
             serial_number: hardware.serial_number,
+            schedule:      period.variant,
+            department:    base.details.department,
+            title:         base.details.title,
+            mail:          base.details.mail,
+            member_of:     base.details.member_of,
+            computer_name: base.computer_name,
+            entry_id:      Uuid::new_v4(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl WorkStationEntry {
+    /// A library PC logon by `username` at `date_time`.
+    pub fn sample(username: &str, date_time: DateTime<Local>) -> Self {
+        Self {
+            username: username.to_string(),
+            user_ou: "Students".to_string(),
+            date_time,
+            period: "Period 3".to_string(),
+            description: "Library PC".to_string(),
+            ws_ou: "LIB".to_string(),
+            os_version: "23H2".to_string(),
+            model: "OptiPlex 7010".to_string(),
+            os: "Windows 11 Pro".to_string(),
+            full_ou: "OU=Computers_LIB".to_string(),
+            make: "Dell Inc.".to_string(),
+            uuid: "4C4C4544-0037-3610-8052-B4C04F4E3032".to_string(),
+            serial_number: "7RB2BG3".to_string(),
+            schedule: "Default".to_string(),
+            department: "Year 9".to_string(),
+            title: String::new(),
+            mail: format!("{username}@school.local"),
+            member_of: "Students; Library".to_string(),
+            computer_name: "LIB-PC-14".to_string(),
+            entry_id: Uuid::new_v4(),
         }
     }
 }
 
+impl WorkStationEntry {
+    /// Whether `other` is this logon, also when it was read back from a workbook, which keeps
+    /// neither the entry id nor the fraction of a second.
+    pub fn same_logon(&self, other: &WorkStationEntry) -> bool {
+        self.entry_id == other.entry_id
+            || (self.username == other.username
+                && self.computer_name == other.computer_name
+                && self.uuid == other.uuid
+                && (self.date_time - other.date_time).num_seconds().abs() <= 1)
+    }
+}
+
+// Namespace of the ids given to rows read back from a workbook.
+const ROW_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6f1c_2a4e_93b7_4d0a_8e35_c7d1_0b5f_9a62);
+
+// The row's cells, as a name for `Uuid::new_v5`.
+fn row_key(row: &[Data]) -> String {
+    row.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\u{1f}")
+}
+
 impl ExcelLoggable for WorkStationEntry {
     const COLUMNS: &'static [&'static str] = &[
         "Username",
@@ -63,30 +138,20 @@ impl ExcelLoggable for WorkStationEntry {
         "Make",
         "UUID",
         "Serial_Number",
+        "Schedule",
+        "Department",
+        "Title",
+        "Mail",
+        "MemberOf",
+        "Computer",
     ];
 
     fn write_entry(&self, ws: &mut Worksheet, row: u32) -> Result<()> {
         #[allow(unused_imports)]
         use rust_xlsxwriter::Format;
 
-        let fields = [
-            &self.username,                   // 0
-            &self.user_ou,                    // 1
-            &String::from("__placeholder__"), // 2 &edt placeholder, overwritten by DT formatted col
-            &self.period,                     // 3
-            &self.description,                // 4
-            &self.ws_ou,                      // 5
-            &self.os_version,                 // 6
-            &self.model,                      // 7
-            &self.os,                         // 8
-            &self.full_ou,                    // 9
-            &self.make,                       // 10
-            &self.uuid,                       // 11
-            &self.serial_number,              // 12
-        ];
-
-        fields.iter().enumerate().for_each(|(i, field)| {
-            ws.write_string(row, i as u16, *field).unwrap_or_else(|e| {
+        self.field_values().iter().enumerate().for_each(|(i, field)| {
+            ws.write_string(row, i as u16, field).unwrap_or_else(|e| {
                 panic!("Failed to write field {}: {}", Self::COLUMNS[i], e);
             });
         });
@@ -94,7 +159,8 @@ impl ExcelLoggable for WorkStationEntry {
         // convert the `edt` datetime from the string insertion format,
         // back to a datetime by.... writing it again as an actual datetime
 
-        let edt = ExcelDateTime::parse_from_str(&self.date_time.to_rfc3339())
+        // Excel has no time zones, the cell holds the local wall clock time
+        let edt = ExcelDateTime::parse_from_str(&self.date_time.format("%Y-%m-%dT%H:%M:%S").to_string())
             .map_err(|e| Error::Generic(format!("Failed to parse date time: {}", e)))?;
 
         // HACK: [dirty] : Extremely dirty way to handle this lol...
@@ -104,6 +170,30 @@ impl ExcelLoggable for WorkStationEntry {
         Ok(())
     }
 
+    fn field_values(&self) -> Vec<String> {
+        vec![
+            self.username.clone(),                                      // 0
+            self.user_ou.clone(),                                       // 1
+            self.date_time.to_rfc3339_opts(SecondsFormat::Secs, false), // 2
+            self.period.clone(),                                        // 3
+            self.description.clone(),                                   // 4
+            self.ws_ou.clone(),                                         // 5
+            self.os_version.clone(),                                    // 6
+            self.model.clone(),                                         // 7
+            self.os.clone(),                                            // 8
+            self.full_ou.clone(),                                       // 9
+            self.make.clone(),                                          // 10
+            self.uuid.clone(),                                          // 11
+            self.serial_number.clone(),                                 // 12
+            self.schedule.clone(),                                      // 13
+            self.department.clone(),                                    // 14
+            self.title.clone(),                                         // 15
+            self.mail.clone(),                                          // 16
+            self.member_of.clone(),                                     // 17
+            self.computer_name.clone(),                                 // 18
+        ]
+    }
+
     fn parse_row(row: &[Data]) -> Option<Self> {
         // NOTE: ?[maybe] : rows.len ??? or is it via columns len?
         if row.len() < 13 {
@@ -112,39 +202,61 @@ impl ExcelLoggable for WorkStationEntry {
 
         // check if the row[2] can be a float, else return None;
 
-        let dt = if row[2].get_float().is_some() {
-            Self::excel_date_to_chrono(row[2].get_float().unwrap())
-        } else {
-            return None;
+        // Written as a datetime, so calamine hands back `DateTime` (older files may hold a plain float)
+        let dt = match &row[2] {
+            Data::Float(serial) => Self::excel_date_to_chrono(*serial),
+            Data::DateTime(edt) => Self::excel_date_to_chrono(edt.as_f64()),
+            _ => return None,
+        };
+
+        // Blank cells come back as `Empty` rather than ""
+        let cell = |i: usize| {
+            match &row[i] {
+                Data::Empty => Some(String::new()),
+                d => d.as_string(),
+            }
         };
+        // Added after the first release, older logs don't have these columns
+        let optional = |i: usize| row.get(i).and_then(|d| d.as_string()).unwrap_or_default();
 
         Some(WorkStationEntry {
-            username:      row[0].as_string()?,
-            user_ou:       row[1].as_string()?,
+            username:      cell(0)?,
+            user_ou:       cell(1)?,
             date_time:     dt,
-            period:        row[3].as_string()?,
-            description:   row[4].as_string()?,
-            ws_ou:         row[5].as_string()?,
-            os_version:    row[6].as_string()?,
-            model:         row[7].as_string()?,
-            os:            row[8].as_string()?,
-            full_ou:       row[9].as_string()?,
-            make:          row[10].as_string()?,
-            uuid:          row[11].as_string()?,
-            serial_number: row[12].as_string()?,
+            period:        cell(3)?,
+            description:   cell(4)?,
+            ws_ou:         cell(5)?,
+            os_version:    cell(6)?,
+            model:         cell(7)?,
+            os:            cell(8)?,
+            full_ou:       cell(9)?,
+            make:          cell(10)?,
+            uuid:          cell(11)?,
+            serial_number: cell(12)?,
+            schedule:      optional(13),
+            department:    optional(14),
+            title:         optional(15),
+            mail:          optional(16),
+            member_of:     optional(17),
+            computer_name: optional(18),
+            // Not kept in the workbook, derived from the row instead so reading it again gives the
+            // same id (and only an identical row shares it)
+            entry_id:      Uuid::new_v5(&ROW_ID_NAMESPACE, row_key(row).as_bytes()),
         })
     }
 
     fn excel_date_to_chrono(serial: f64) -> DateTime<Local> {
-        let unix = ((serial - 25569.0) * 86400.0).round() as i64;
-        // let mut utc = chrono::Utc::now();
-        // let mut local = chrono::Local::now();
+        // Serial days since 1899-12-30, in local time (see `write_entry`)
+        let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)
+            .expect("valid date")
+            .and_time(NaiveTime::MIN);
+        let naive = epoch + TimeDelta::seconds((serial * 86400.0).round() as i64);
 
+        // A wall clock time skipped by a DST change can't be local, keep it as written
         chrono::Local
-            .timestamp_opt(unix, 0)
-            .single()
-            .expect("Invalid excel date")
-            .with_timezone(&chrono::Local)
+            .from_local_datetime(&naive)
+            .earliest()
+            .unwrap_or_else(|| chrono::Local.from_utc_datetime(&naive))
     }
 }
 
@@ -169,6 +281,12 @@ impl FieldLengsths for WorkStationEntry {
             self.make.len(),
             self.uuid.len(),
             self.serial_number.len(),
+            self.schedule.len(),
+            self.department.len(),
+            self.title.len(),
+            self.mail.len(),
+            self.member_of.len(),
+            self.computer_name.len(),
         ]
     }
 }
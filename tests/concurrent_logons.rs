@@ -0,0 +1,96 @@
+//! Many machines logging on at once, as separate processes sharing one workbook and its journal.
+
+use std::path::Path;
+use std::process::Command;
+
+const PROCESSES: usize = 12;
+
+const FIXTURE: &str = r#"
+[base]
+computer_name = "LIB-PC-14"
+username = "jsmith"
+user_ou = "Students"
+full_ou = "OU=Computers_LIB"
+ws_ou = "LIB"
+[hardware]
+make = "Dell"
+model = "OptiPlex"
+uuid = "1234"
+serial_number = "ABC"
+os_description = "Library PC"
+[os]
+os_version = "23H2"
+os_name = "Windows 11 Pro"
+"#;
+
+fn write_config(dir: &Path) -> std::path::PathBuf {
+    std::fs::write(dir.join("fixture.toml"), FIXTURE).unwrap();
+    let config = dir.join("logon_logger.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+ws_base_path = "{ws}"
+user_base_path = "{user}"
+
+[collectors]
+base = "fixture"
+hardware = "fixture"
+os = "fixture"
+fixture = "fixture.toml"
+
+[lock]
+wait_secs = 120
+poll_ms = 20
+
+[spool]
+dir = "{spool}"
+
+# Compacting every few entries, so appends race compactions as well as each other
+[[sinks]]
+type = "xlsx"
+family = "workstation"
+compact_after = 4
+"#,
+            ws = dir.join("ws").display(),
+            user = dir.join("user").display(),
+            spool = dir.join("spool").display(),
+        ),
+    )
+    .unwrap();
+    config
+}
+
+#[test]
+fn concurrent_logons_lose_no_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = write_config(dir.path());
+
+    let children: Vec<_> = (0..PROCESSES)
+        .map(|_| {
+            Command::new(env!("CARGO_BIN_EXE_logon_logger"))
+                .arg("--config")
+                .arg(&config)
+                .arg("log")
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    // Nothing was left for a later run to deliver
+    let spooled = std::fs::read_dir(dir.path().join("spool")).map_or(0, |dir| dir.count());
+    assert_eq!(spooled, 0);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_logon_logger"))
+        .arg("--config")
+        .arg(&config)
+        .args(["validate", "--family", "workstation"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains(&format!("({PROCESSES} entries)")), "{stdout}");
+}